@@ -1,5 +1,21 @@
-use crate::{Node, Trace};
+use crate::{Cascade, Node, NodeRef, Trace, WeakNode};
 use isize_vec::IsizeVec;
+#[cfg(feature = "sampling")]
+use rand::{seq::index, Rng};
+#[cfg(feature = "profiling")]
+use std::time::Duration;
+#[cfg(any(feature = "profiling", feature = "timestamps"))]
+use std::time::Instant;
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    error::Error,
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+};
 
 /// Container for multiple [Node]s.
 ///
@@ -17,8 +33,18 @@ use isize_vec::IsizeVec;
 /// });
 /// ```
 pub struct Channel<T: ?Sized> {
-    items: IsizeVec<Node<T>>,
+    items: IsizeVec<Entry<T>>,
+    generation: u64,
+    insertions: u64,
+    retired: Vec<(WeakNode<T>, u64)>,
+    limit: Option<usize>,
     trace: Trace,
+    panic_observer: Option<RefCell<PanicObserver>>,
+    depth: Cell<usize>,
+    replay: VecDeque<Replay<T>>,
+    replay_limit: usize,
+    middleware: Vec<Middleware>,
+    fallback: Option<Node<T>>,
 }
 
 impl<T: ?Sized> Default for Channel<T> {
@@ -32,7 +58,17 @@ impl<T: ?Sized> Channel<T> {
     pub fn new() -> Self {
         Self {
             items: IsizeVec::default(),
+            generation: 0,
+            insertions: 0,
+            retired: Vec::new(),
+            limit: None,
             trace: Trace::empty(),
+            panic_observer: None,
+            depth: Cell::new(0),
+            replay: VecDeque::new(),
+            replay_limit: 0,
+            middleware: Vec::new(),
+            fallback: None,
         }
     }
 
@@ -40,7 +76,17 @@ impl<T: ?Sized> Channel<T> {
     pub fn new_with_trace(trace: impl Fn(usize) + 'static) -> Self {
         Self {
             items: IsizeVec::default(),
+            generation: 0,
+            insertions: 0,
+            retired: Vec::new(),
+            limit: None,
             trace: Trace::new(trace),
+            panic_observer: None,
+            depth: Cell::new(0),
+            replay: VecDeque::new(),
+            replay_limit: 0,
+            middleware: Vec::new(),
+            fallback: None,
         }
     }
 
@@ -49,120 +95,2258 @@ impl<T: ?Sized> Channel<T> {
     /// The value `relative` indicates where the node will be put in the list relative to other
     /// nodes. If two nodes have the same `relative` value, then the node will be prepended if it
     /// is signed, and appended if unsigned.
+    ///
+    /// # Panics #
+    ///
+    /// Panics if the channel already holds as many nodes as allowed by
+    /// [set_capacity_limit](Channel::set_capacity_limit).
+    ///
+    /// Panics if values are to be replayed (see [set_replay](Channel::set_replay)) and the node
+    /// is currently being emitted to.
     pub fn insert(&mut self, relative: isize, item: Node<T>) {
-        self.items.insert(relative, item);
+        if self.try_insert(relative, item).is_err() {
+            panic!("revent: insert: channel capacity limit exceeded");
+        }
+    }
+
+    /// Insert a node into this channel unless it would exceed the capacity limit.
+    ///
+    /// See [insert](Channel::insert) for the meaning of `relative`. Returns an error if the
+    /// channel already holds as many nodes as allowed by
+    /// [set_capacity_limit](Channel::set_capacity_limit).
+    pub fn try_insert(&mut self, relative: isize, item: Node<T>) -> Result<(), CapacityError> {
+        if let Some(max) = self.limit {
+            let current = self.items.iter().count();
+            if current >= max {
+                return Err(CapacityError { current, max });
+            }
+        }
+        for replay in &self.replay {
+            item.emit(|x| (replay)(x));
+        }
+        #[cfg(feature = "log")]
+        log::debug!(
+            "revent: insert: {} into channel at {}",
+            Node::type_name(&item),
+            relative
+        );
+        let generation = self.generation_of(&item);
+        let insertion = self.next_insertion();
+        self.items.insert(
+            relative,
+            Entry {
+                relative,
+                generation,
+                insertion,
+                #[cfg(feature = "timestamps")]
+                inserted: Instant::now(),
+                tags: Vec::new(),
+                node: item,
+            },
+        );
+        Ok(())
+    }
+
+    /// Replace every node in this channel with `nodes`, emitted to in the given order.
+    ///
+    /// The new nodes are inserted with a `relative` value of 0 and count as newly inserted
+    /// for [emit_by_generation](Channel::emit_by_generation). No handlers run on either the
+    /// replaced or the new nodes, so recorded values are not replayed.
+    ///
+    /// # Panics #
+    ///
+    /// Panics if `nodes` holds more nodes than allowed by
+    /// [set_capacity_limit](Channel::set_capacity_limit).
+    pub fn set_nodes(&mut self, nodes: Vec<Node<T>>) {
+        if let Some(limit) = self.limit {
+            if nodes.len() > limit {
+                panic!("revent: set_nodes: channel capacity limit exceeded");
+            }
+        }
+
+        let mut entries = Vec::with_capacity(nodes.len());
+        for node in nodes {
+            #[cfg(feature = "log")]
+            log::debug!("revent: set_nodes: {} into channel", Node::type_name(&node));
+            let insertion = self.next_insertion();
+            entries.push(Entry {
+                relative: 0,
+                generation: self.generation,
+                insertion,
+                #[cfg(feature = "timestamps")]
+                inserted: Instant::now(),
+                tags: Vec::new(),
+                node,
+            });
+            self.generation += 1;
+        }
+        self.rebuild(entries);
+    }
+
+    /// Move all nodes from `other` into this channel, leaving `other` empty.
+    ///
+    /// Nodes keep their `relative` values and tags, and follow this channel's nodes that share
+    /// the same `relative` value. They count as newly inserted for
+    /// [emit_by_generation](Channel::emit_by_generation), and recorded values are not replayed
+    /// to them.
+    ///
+    /// # Panics #
+    ///
+    /// Panics if the combined number of nodes exceeds the capacity limit of this channel.
+    pub fn append(&mut self, other: &mut Channel<T>) {
+        let mut entries = self.items.iter().cloned().collect::<Vec<_>>();
+        let mut appended = other.items.iter().cloned().collect::<Vec<_>>();
+
+        if let Some(limit) = self.limit {
+            if entries.len() + appended.len() > limit {
+                panic!("revent: append: channel capacity limit exceeded");
+            }
+        }
+
+        let mut by_generation = (0..appended.len()).collect::<Vec<_>>();
+        by_generation.sort_by_key(|x| appended[*x].generation);
+        for index in by_generation {
+            #[cfg(feature = "log")]
+            log::debug!(
+                "revent: append: {} into channel",
+                Node::type_name(&appended[index].node)
+            );
+            appended[index].generation = self.generation;
+            appended[index].insertion = self.next_insertion();
+            self.generation += 1;
+        }
+        entries.extend(appended);
+
+        self.rebuild(entries);
+        other.items = IsizeVec::default();
+    }
+
+    /// Remove all occurrences of a node from this channel.
+    ///
+    /// # Performance #
+    ///
+    /// Performs a linear scan and retains only those nodes that do not match. The channel
+    /// remembers up to 64 removed nodes by weak reference, see
+    /// [emit_by_generation](Channel::emit_by_generation). A remembered node whose contents were
+    /// dropped keeps its allocation until the next insertion or removal on this channel.
+    pub fn remove(&mut self, item: &Node<T>) {
+        #[cfg(feature = "log")]
+        log::debug!("revent: remove: {} from channel", Node::type_name(item));
+        self.retire(|x| Node::<T>::ptr_eq(item, &x.node));
+    }
+
+    /// Get the node that has been in this channel the longest.
+    ///
+    /// Requires the `timestamps` feature. Returns `None` if the channel is empty.
+    #[cfg(feature = "timestamps")]
+    pub fn oldest(&self) -> Option<Node<T>> {
+        self.items
+            .iter()
+            .min_by_key(|x| x.inserted)
+            .map(|x| x.node.clone())
+    }
+
+    /// Get the time at which a node was inserted into this channel.
+    ///
+    /// Requires the `timestamps` feature. If the node occurs multiple times, the earliest
+    /// insertion is returned. Returns `None` if the node is not in this channel.
+    #[cfg(feature = "timestamps")]
+    pub fn inserted_at(&self, item: &Node<T>) -> Option<Instant> {
+        self.items
+            .iter()
+            .filter(|x| Node::ptr_eq(item, &x.node))
+            .map(|x| x.inserted)
+            .min()
+    }
+
+    /// Replace all occurrences of `old` with `new`, keeping their position in this channel.
+    ///
+    /// Returns `false` if `old` is not in this channel.
+    pub fn replace(&mut self, old: &Node<T>, new: Node<T>) -> bool {
+        #[cfg(feature = "log")]
+        let name = Node::type_name(&new);
+        let replaced = self.modify(old, |entry| entry.node = new.clone());
+        #[cfg(feature = "log")]
+        {
+            if replaced {
+                log::debug!("revent: replace: {} into channel", name);
+            }
+        }
+        replaced
+    }
+
+    /// Attach a tag to all occurrences of a node in this channel.
+    ///
+    /// Tags are used by [emit_tagged](Channel::emit_tagged). Returns `false` if the node is
+    /// not in this channel.
+    pub fn add_tag(&mut self, item: &Node<T>, tag: &str) -> bool {
+        self.modify(item, |entry| entry.tags.push(tag.to_string()))
+    }
+
+    /// Move all occurrences of a node to the front of this channel.
+    ///
+    /// The moved node is given a `relative` value of [isize::MIN], so it is only preceded by
+    /// nodes later inserted with that same value. Returns `false` if the node is not in this
+    /// channel.
+    pub fn move_to_front(&mut self, item: &Node<T>) -> bool {
+        let (mut moved, rest) = self.partition(item);
+        if moved.is_empty() {
+            return false;
+        }
+        for entry in &mut moved {
+            entry.relative = isize::MIN;
+        }
+        moved.extend(rest);
+        self.rebuild(moved);
+        true
+    }
+
+    /// Move all occurrences of a node to the back of this channel.
+    ///
+    /// The moved node is given a `relative` value of [isize::MAX], so it is only followed by
+    /// nodes later inserted with that same value. Returns `false` if the node is not in this
+    /// channel.
+    pub fn move_to_back(&mut self, item: &Node<T>) -> bool {
+        let (moved, mut rest) = self.partition(item);
+        if moved.is_empty() {
+            return false;
+        }
+        rest.extend(moved.into_iter().map(|mut entry| {
+            entry.relative = isize::MAX;
+            entry
+        }));
+        self.rebuild(rest);
+        true
+    }
+
+    /// Create a [WeakNode] for each node in this channel, in emission order.
+    ///
+    /// The weak nodes do not keep their contents alive and must be
+    /// [upgrade](WeakNode::upgrade)d before use, which fails once the contents are dropped
+    /// after being removed from this channel.
+    pub fn weak_handles(&self) -> Vec<WeakNode<T>> {
+        self.items
+            .iter()
+            .map(|x| Node::downgrade(&x.node))
+            .collect()
+    }
+
+    /// Get the [type name](Node::type_name) of each node in this channel, in emission order.
+    ///
+    /// Useful for inspecting the composition of a `Channel<dyn Trait>`.
+    pub fn subscriber_names(&self) -> Vec<&'static str> {
+        self.items
+            .iter()
+            .map(|x| Node::type_name(&x.node))
+            .collect()
+    }
+
+    /// Iterate over shared references to the items in this channel, in emission order.
+    ///
+    /// Each item stays borrowed while its [NodeRef] is alive, see [Node::borrow].
+    ///
+    /// ```
+    /// use revent::{Channel, Node};
+    ///
+    /// let mut channel = Channel::new();
+    /// for number in 0..10 {
+    ///     channel.insert(0, Node::new(number));
+    /// }
+    ///
+    /// assert_eq!(channel.iter().filter(|x| **x % 2 == 0).count(), 5);
+    /// ```
+    pub fn iter(&self) -> impl Iterator<Item = NodeRef<'_, T>> {
+        self.items.iter().map(|x| x.node.borrow())
+    }
+
+    /// Collect the nodes whose items satisfy a predicate, in emission order.
+    ///
+    /// Each item is only [borrow](Node::borrow)ed while the predicate runs, so selecting is not
+    /// an emission. The returned nodes can be emitted to afterwards without holding on to this
+    /// channel.
+    ///
+    /// # Panics #
+    ///
+    /// Panics if one of the nodes is currently being emitted to without being suspended.
+    pub fn select(&self, predicate: impl Fn(&T) -> bool) -> Vec<Node<T>> {
+        self.items
+            .iter()
+            .filter(|x| (predicate)(&x.node.borrow()))
+            .map(|x| x.node.clone())
+            .collect()
+    }
+
+    /// Limit the number of nodes this channel accepts.
+    ///
+    /// Channels are unlimited by default. Nodes already present are kept even if they exceed
+    /// the new limit.
+    pub fn set_capacity_limit(&mut self, max: usize) {
+        self.limit = Some(max);
+    }
+
+    /// Set a function to be called when a handler panics during [emit](Channel::emit).
+    ///
+    /// The observer receives the index of the node that was being emitted to and is called
+    /// while unwinding, after which the panic continues to propagate.
+    pub fn set_panic_observer(&mut self, observer: impl FnMut(usize) + 'static) {
+        self.panic_observer = Some(RefCell::new(Box::new(observer)));
+    }
+
+    /// Wrap every emission on this channel in a middleware function.
+    ///
+    /// This covers [emit](Channel::emit) and every other `emit_*` method,
+    /// [try_emit](Channel::try_emit), [EmitBuilder::run], and each step of an [EmitStepper].
+    /// [select](Channel::select) and [iter](Channel::iter) only borrow items and are not
+    /// wrapped.
+    ///
+    /// The middleware receives a `next` thunk running the rest of the chain, and may call it
+    /// any number of times. If `next` is not called, no item is visited and the emission
+    /// returns what it would for an empty channel. Middleware added first is outermost, so it
+    /// runs before and finishes after middleware added later. Panics from handlers propagate
+    /// through every middleware unless one of them catches it.
+    ///
+    /// ```
+    /// use revent::{Channel, Node};
+    ///
+    /// let mut channel = Channel::new();
+    /// channel.insert(0, Node::new(0));
+    ///
+    /// channel.add_middleware(|next| {
+    ///     println!("before");
+    ///     next();
+    ///     println!("after");
+    /// });
+    ///
+    /// channel.emit(|x| *x += 1);
+    /// ```
+    pub fn add_middleware(&mut self, middleware: impl Fn(&mut dyn FnMut()) + 'static) {
+        self.middleware.push(Box::new(middleware));
+    }
+
+    /// Apply a function to each item in this channel.
+    pub fn emit(&self, mut handler: impl FnMut(&mut T)) {
+        self.emission((), || {
+            for (index, item) in self.items.iter().enumerate() {
+                let _guard = self.panic_observer.as_ref().map(|observer| PanicGuard {
+                    observer,
+                    index,
+                    panicking: thread::panicking(),
+                });
+                item.node.emit(|x| {
+                    (handler)(x);
+                });
+            }
+        });
+    }
+
+    /// Apply a function to each item in this channel and check that each was visited once.
+    ///
+    /// Handlers cannot skip items on their own, but a [middleware](Channel::add_middleware)
+    /// may call `next` any number of times. The invariant checked is that the handler ran
+    /// exactly as many times as there were nodes when the emission started. This is a testing
+    /// aid: without debug assertions it behaves like [emit](Channel::emit).
+    ///
+    /// # Panics #
+    ///
+    /// With debug assertions, panics after the emission if the handler count does not match.
+    pub fn emit_assert_complete(&self, mut handler: impl FnMut(&mut T)) {
+        let present = self.items.iter().count();
+        let mut visited = 0;
+        self.emit(|x| {
+            visited += 1;
+            (handler)(x);
+        });
+
+        if cfg!(debug_assertions) && visited != present {
+            panic!(
+                "revent: emit_assert_complete: handler ran {} times for {} nodes",
+                visited, present
+            );
+        }
+    }
+
+    /// Keep the last `count` values sent by [emit_value](Channel::emit_value) and replay them
+    /// to nodes as they are inserted.
+    ///
+    /// A `count` of 0, the default, disables replaying. Values beyond the new count are
+    /// discarded, oldest first.
+    pub fn set_replay(&mut self, count: usize) {
+        self.replay_limit = count;
+        while self.replay.len() > count {
+            self.replay.pop_front();
+        }
+    }
+
+    /// Apply a function with a value to each item in this channel, and record the value for
+    /// replaying.
+    ///
+    /// Each item receives its own clone of `value`. If replaying is enabled via
+    /// [set_replay](Channel::set_replay), nodes inserted later receive the recorded values in
+    /// the order they were originally emitted.
+    pub fn emit_value<V: Clone + 'static>(
+        &mut self,
+        value: V,
+        handler: impl Fn(&mut T, V) + 'static,
+    ) {
+        self.emit(|x| (handler)(x, value.clone()));
+
+        if self.replay_limit > 0 {
+            if self.replay.len() == self.replay_limit {
+                self.replay.pop_front();
+            }
+            self.replay
+                .push_back(Box::new(move |x| (handler)(x, value.clone())));
+        }
+    }
+
+    /// Apply a function to each item in this channel, returning whether any item was visited.
+    pub fn emit_any(&self, mut handler: impl FnMut(&mut T)) -> bool {
+        self.emission(false, || {
+            let mut any = false;
+            for item in self.items.iter() {
+                item.node.emit(|x| {
+                    (handler)(x);
+                });
+                any = true;
+            }
+            any
+        })
+    }
+
+    /// Apply a function to each item in this channel and remove those for which it returns
+    /// `false`.
+    ///
+    /// Removal is deferred until every item has been visited, so the emission itself sees the
+    /// channel unchanged.
+    pub fn emit_retaining(&mut self, mut handler: impl FnMut(&mut T) -> bool) {
+        let removed = self.emission(Vec::new(), || {
+            let mut removed = Vec::new();
+            for item in self.items.iter() {
+                if !item.node.emit(|x| (handler)(x)) {
+                    removed.push(item.generation);
+                }
+            }
+            removed
+        });
+
+        if !removed.is_empty() {
+            self.retire(|x| removed.contains(&x.generation));
+        }
+    }
+
+    /// Apply a function to each item in this channel whose key equals `key`.
+    ///
+    /// The key of each item is computed by `key_of` right before the item would be visited.
+    pub fn emit_group_by<K: Eq>(
+        &self,
+        key: K,
+        key_of: impl Fn(&T) -> K,
+        mut handler: impl FnMut(&mut T),
+    ) {
+        self.emission((), || {
+            for item in self.items.iter() {
+                item.node.emit(|x| {
+                    if (key_of)(x) == key {
+                        (handler)(x);
+                    }
+                });
+            }
+        });
+    }
+
+    /// Apply a function to each item in this channel carrying `tag`.
+    ///
+    /// Tags are attached using [add_tag](Channel::add_tag).
+    pub fn emit_tagged(&self, tag: &str, mut handler: impl FnMut(&mut T)) {
+        self.emission((), || {
+            for item in self.items.iter() {
+                if item.tags.iter().any(|x| x == tag) {
+                    item.node.emit(|x| {
+                        (handler)(x);
+                    });
+                }
+            }
+        });
+    }
+
+    /// Apply a function to each item in this channel in the order they were inserted.
+    ///
+    /// Unlike [emit](Channel::emit), the `relative` value is ignored. Nodes keep their place in
+    /// this order when moved or replaced, and a node that is removed and inserted again returns
+    /// to its original place as long as its contents were kept alive in between and it is among
+    /// the last 64 nodes removed from this channel. Nodes added
+    /// by [append](Channel::append) or [set_nodes](Channel::set_nodes) count as newly
+    /// inserted.
+    pub fn emit_by_generation(&self, mut handler: impl FnMut(&mut T)) {
+        self.emission((), || {
+            let mut items = self.items.iter().collect::<Vec<_>>();
+            items.sort_unstable_by_key(|x| x.generation);
+            for item in items {
+                item.node.emit(|x| {
+                    (handler)(x);
+                });
+            }
+        });
+    }
+
+    /// Apply a function to the items in this channel in the given order.
+    ///
+    /// # Panics #
+    ///
+    /// Panics if an index in [EmitOrder::Custom] is not less than the number of nodes in this
+    /// channel. No item is visited in that case.
+    pub fn emit_ordered(&self, order: EmitOrder, mut handler: impl FnMut(&mut T)) {
+        let items = self.items.iter().collect::<Vec<_>>();
+        let indices = match order {
+            EmitOrder::Forward => (0..items.len()).collect(),
+            EmitOrder::Reverse => (0..items.len()).rev().collect(),
+            EmitOrder::Custom(indices) => {
+                if indices.iter().any(|x| *x >= items.len()) {
+                    panic!("revent: emit_ordered: index out of bounds");
+                }
+                indices
+            }
+        };
+
+        self.emission((), || {
+            for index in &indices {
+                items[*index].node.emit(|x| {
+                    (handler)(x);
+                });
+            }
+        });
+    }
+
+    /// Apply a function to each item in this channel in a shuffled order determined by `seed`.
+    ///
+    /// The same seed and the same nodes always give the same order, so varying the seed per
+    /// frame spreads which item is visited first while staying reproducible.
+    pub fn emit_shuffled(&self, seed: u64, mut handler: impl FnMut(&mut T)) {
+        let mut items = self.items.iter().collect::<Vec<_>>();
+        // Fisher-Yates shuffle driven by xorshift64, which must not start from 0.
+        let mut state = (seed ^ 0x9e37_79b9_7f4a_7c15).max(1);
+        for index in (1..items.len()).rev() {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            items.swap(index, (state % (index as u64 + 1)) as usize);
+        }
+
+        self.emission((), || {
+            for item in &items {
+                item.node.emit(|x| {
+                    (handler)(x);
+                });
+            }
+        });
+    }
+
+    /// Apply a function to each item in this channel together with some external state.
+    ///
+    /// Equivalent to [emit](Channel::emit) but passes `state` alongside each item, avoiding
+    /// the need to capture `&mut` state in the handler.
+    pub fn emit_state<S>(&self, state: &mut S, mut handler: impl FnMut(&mut S, &mut T)) {
+        self.emission((), || {
+            for item in self.items.iter() {
+                item.node.emit(|x| {
+                    (handler)(state, x);
+                });
+            }
+        });
+    }
+
+    /// Apply a function to each item in this channel except `exclude`.
+    ///
+    /// Nodes are compared using [Node::ptr_eq]. Useful when a node emits into a channel it is
+    /// part of and does not want to receive its own signal.
+    pub fn emit_except(&self, exclude: &Node<T>, mut handler: impl FnMut(&mut T)) {
+        self.emission((), || {
+            for item in self.items.iter() {
+                if Node::ptr_eq(exclude, &item.node) {
+                    continue;
+                }
+                item.node.emit(|x| {
+                    (handler)(x);
+                });
+            }
+        });
+    }
+
+    /// Apply a function to each item in this channel and measure the time spent on each.
+    ///
+    /// Requires the `profiling` feature. Returns the durations in emission order.
+    #[cfg(feature = "profiling")]
+    pub fn emit_timed(&self, mut handler: impl FnMut(&mut T)) -> Vec<Duration> {
+        self.emission(Vec::new(), || {
+            let mut durations = Vec::new();
+            for item in self.items.iter() {
+                let start = Instant::now();
+                item.node.emit(|x| {
+                    (handler)(x);
+                });
+                durations.push(start.elapsed());
+            }
+            durations
+        })
+    }
+
+    /// Apply a function to a random subset of the items in this channel.
+    ///
+    /// Requires the `sampling` feature. Visits `fraction` of the items rounded to the nearest
+    /// whole item, chosen uniformly using `rng` and visited in emission order.
+    ///
+    /// # Panics #
+    ///
+    /// Panics if `fraction` is not within `0.0..=1.0`.
+    #[cfg(feature = "sampling")]
+    pub fn emit_sample(&self, fraction: f64, rng: &mut impl Rng, mut handler: impl FnMut(&mut T)) {
+        if !(0.0..=1.0).contains(&fraction) {
+            panic!("revent: emit_sample: fraction must be within 0.0 and 1.0");
+        }
+
+        let items = self.items.iter().collect::<Vec<_>>();
+        let amount = (fraction * items.len() as f64).round() as usize;
+        let mut chosen = index::sample(rng, items.len(), amount).into_vec();
+        chosen.sort_unstable();
+
+        self.emission((), || {
+            for index in &chosen {
+                items[*index].node.emit(|x| {
+                    (handler)(x);
+                });
+            }
+        });
+    }
+
+    /// Create an [EmitBuilder] for combining filtering, ordering, and cancellation.
+    ///
+    /// ```
+    /// use revent::{Channel, Node};
+    /// use std::sync::atomic::AtomicBool;
+    ///
+    /// let mut channel = Channel::new();
+    /// for number in 0..10 {
+    ///     channel.insert(0, Node::new(number));
+    /// }
+    ///
+    /// let token = AtomicBool::new(false);
+    /// channel
+    ///     .emit_builder()
+    ///     .filtered(|x| x % 2 == 0)
+    ///     .ordered_by(|x| -x)
+    ///     .cancellable(&token)
+    ///     .run(|x| println!("{}", x));
+    /// ```
+    pub fn emit_builder(&self) -> PlainEmitBuilder<'_, T> {
+        let filter: fn(&T) -> bool = |_| true;
+        EmitBuilder {
+            channel: self,
+            filter,
+            order: None,
+            token: None,
+        }
+    }
+
+    /// Create an [EmitStepper] which visits the items of this channel one at a time.
+    ///
+    /// The stepper borrows this channel, so no nodes can be inserted or removed until it is
+    /// dropped.
+    pub fn emit_stepwise(&self) -> EmitStepper<'_, T> {
+        EmitStepper {
+            channel: self,
+            items: self.items.iter().map(|x| &x.node).collect(),
+            next: 0,
+        }
+    }
+
+    /// Apply a fallible function to each item in this channel, stopping at the first error.
+    ///
+    /// Items after the one that failed are not visited.
+    pub fn try_emit<E>(&self, mut handler: impl FnMut(&mut T) -> Result<(), E>) -> Result<(), E> {
+        self.emission(Ok(()), || {
+            for item in self.items.iter() {
+                item.node.emit(|x| (handler)(x))?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Apply a function to each item in this channel until one claims the event.
+    ///
+    /// An item claims the event when `handler` returns `true`, after which no further items
+    /// are visited. If no item claims it, `handler` is applied to the node set by
+    /// [set_fallback](Channel::set_fallback), which always comes last and only runs in that
+    /// case. Returns whether an item or the fallback claimed the event.
+    ///
+    /// ```
+    /// use revent::{Channel, Node};
+    ///
+    /// let mut channel = Channel::new();
+    /// channel.insert(0, Node::new(String::from("png")));
+    /// channel.set_fallback(Node::new(String::from("*")));
+    ///
+    /// let mut handled_by = None;
+    /// channel.emit_find(|x| {
+    ///     if x == "png" || x == "*" {
+    ///         handled_by = Some(x.clone());
+    ///         return true;
+    ///     }
+    ///     false
+    /// });
+    /// assert_eq!(handled_by.as_deref(), Some("png"));
+    /// ```
+    pub fn emit_find(&self, mut handler: impl FnMut(&mut T) -> bool) -> bool {
+        self.emission(false, || {
+            for item in self.items.iter() {
+                if item.node.emit(|x| (handler)(x)) {
+                    return true;
+                }
+            }
+            match &self.fallback {
+                Some(fallback) => fallback.emit(|x| (handler)(x)),
+                None => false,
+            }
+        })
+    }
+
+    /// Set the node that [emit_find](Channel::emit_find) falls back on when no item claims an
+    /// event.
+    ///
+    /// The fallback is not one of the items of this channel, so no other emission visits it.
+    /// Replaces any previous fallback.
+    pub fn set_fallback(&mut self, fallback: Node<T>) {
+        self.fallback = Some(fallback);
+    }
+
+    /// Apply a fallible function to each item in this channel and collect all errors.
+    ///
+    /// Every item is visited regardless of earlier failures. Returns an empty vector if all
+    /// items succeeded.
+    pub fn emit_collect<E>(&self, mut handler: impl FnMut(&mut T) -> Result<(), E>) -> Vec<E> {
+        self.emission(Vec::new(), || {
+            let mut errors = Vec::new();
+            for item in self.items.iter() {
+                if let Err(error) = item.node.emit(|x| (handler)(x)) {
+                    errors.push(error);
+                }
+            }
+            errors
+        })
+    }
+
+    /// Prepare every item in this channel and commit them only if all preparations succeed.
+    ///
+    /// `prepare` is applied to each item in order, stopping at and returning the first error.
+    /// Only if every item was prepared successfully is `commit` applied to each item. Side
+    /// effects of `prepare` are not rolled back on failure.
+    pub fn emit_two_phase<E>(
+        &self,
+        mut prepare: impl FnMut(&mut T) -> Result<(), E>,
+        mut commit: impl FnMut(&mut T),
+    ) -> Result<(), E> {
+        self.emission(Ok(()), || {
+            let mut result = Ok(());
+            for item in self.items.iter() {
+                result = item.node.emit(|x| (prepare)(x));
+                if result.is_err() {
+                    break;
+                }
+            }
+
+            if result.is_ok() {
+                for item in self.items.iter() {
+                    item.node.emit(|x| {
+                        (commit)(x);
+                    });
+                }
+            }
+
+            result
+        })
+    }
+
+    /// Apply a predicate to each item in this channel and record each result.
+    ///
+    /// Every item is visited. The returned vector holds one entry per item in emission order.
+    pub fn emit_mask(&self, mut handler: impl FnMut(&mut T) -> bool) -> Vec<bool> {
+        self.emission(Vec::new(), || {
+            self.items
+                .iter()
+                .map(|item| item.node.emit(|x| (handler)(x)))
+                .collect()
+        })
+    }
+
+    /// Apply a function to each item in this channel and write the results into `out`.
+    ///
+    /// Does not allocate. Items are visited in order until `out` is full; items beyond
+    /// `out.len()` are not visited. Returns the number of results written.
+    pub fn emit_into<R>(&self, out: &mut [R], mut handler: impl FnMut(&mut T) -> R) -> usize {
+        self.emission(0, || {
+            let mut written = 0;
+            for (slot, item) in out.iter_mut().zip(self.items.iter()) {
+                *slot = item.node.emit(|x| (handler)(x));
+                written += 1;
+            }
+            written
+        })
+    }
+
+    /// Apply a function to each item in this channel unless `token` is set.
+    ///
+    /// The token is checked before each item using [Ordering::Acquire], so writes made by the
+    /// thread that set the token are visible once cancellation is observed. Returns `false` if
+    /// the emission was cancelled before visiting every item.
+    pub fn emit_cancellable(&self, token: &AtomicBool, mut handler: impl FnMut(&mut T)) -> bool {
+        self.emission(true, || {
+            let mut completed = true;
+            for item in self.items.iter() {
+                if token.load(Ordering::Acquire) {
+                    completed = false;
+                    break;
+                }
+                item.node.emit(|x| {
+                    (handler)(x);
+                });
+            }
+            completed
+        })
+    }
+
+    /// Apply a function to each item in this channel, continuing past panicking handlers.
+    ///
+    /// Every item is visited even if a handler panics. Returns the payload of the first panic,
+    /// subsequent payloads are dropped.
+    ///
+    /// The handler is treated as [UnwindSafe](std::panic::UnwindSafe) on the caller's behalf.
+    /// After a panic, the node being visited and any state captured by the handler may be left
+    /// partially modified and must remain valid to use in that condition.
+    pub fn emit_continue_on_panic(
+        &self,
+        mut handler: impl FnMut(&mut T),
+    ) -> Option<Box<dyn Any + Send>> {
+        self.emission(None, || {
+            let mut first = None;
+            for item in self.items.iter() {
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    item.node.emit(|x| {
+                        (handler)(x);
+                    });
+                }));
+                if let Err(payload) = result {
+                    first.get_or_insert(payload);
+                }
+            }
+            first
+        })
+    }
+
+    /// Apply a function to each item in this channel and remove those whose handler panics.
+    ///
+    /// Panics are swallowed by design; every item is visited and the number of removed items
+    /// is returned. Removal is deferred until every item has been visited.
+    ///
+    /// The same [UnwindSafe](std::panic::UnwindSafe) caveats as
+    /// [emit_continue_on_panic](Channel::emit_continue_on_panic) apply to captured state.
+    pub fn emit_prune_panicking(&mut self, mut handler: impl FnMut(&mut T)) -> usize {
+        let pruned = self.emission(Vec::new(), || {
+            let mut pruned = Vec::new();
+            for item in self.items.iter() {
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    item.node.emit(|x| {
+                        (handler)(x);
+                    });
+                }));
+                if result.is_err() {
+                    pruned.push(item.generation);
+                }
+            }
+            pruned
+        });
+
+        if !pruned.is_empty() {
+            self.retire(|x| pruned.contains(&x.generation));
+        }
+        pruned.len()
+    }
+
+    /// Apply a function to the most recently inserted node in this channel.
+    ///
+    /// Useful for sending an initial state to a node right after inserting it. A node that was
+    /// removed and inserted again counts as inserted at the latter point. Returns `false` if the
+    /// channel is empty.
+    pub fn emit_last(&self, handler: impl FnOnce(&mut T)) -> bool {
+        let mut handler = Some(handler);
+        self.emission(false, || {
+            let newest = self.items.iter().max_by_key(|x| x.insertion);
+            if let (Some(item), Some(handler)) = (newest, handler.take()) {
+                item.node.emit(handler);
+            }
+            newest.is_some()
+        })
+    }
+
+    /// Apply a function to at most the first `count` items in this channel.
+    ///
+    /// Returns the number of items visited, which is less than `count` only if the channel
+    /// holds fewer items.
+    pub fn emit_take(&self, count: usize, mut handler: impl FnMut(&mut T)) -> usize {
+        self.emission(0, || {
+            let mut visited = 0;
+            for item in self.items.iter().take(count) {
+                item.node.emit(|x| (handler)(x));
+                visited += 1;
+            }
+            visited
+        })
+    }
+
+    /// Thread an accumulator through each item in this channel, recording every step.
+    ///
+    /// `handler` receives the previous accumulator, starting with `init`, and returns the next
+    /// one. The returned vector holds the accumulator after each item, in emission order.
+    pub fn emit_scan<A>(&self, init: A, mut handler: impl FnMut(&A, &mut T) -> A) -> Vec<A> {
+        self.emission(Vec::new(), || {
+            let mut steps: Vec<A> = Vec::new();
+            for item in self.items.iter() {
+                let next = item
+                    .node
+                    .emit(|x| (handler)(steps.last().unwrap_or(&init), x));
+                steps.push(next);
+            }
+            steps
+        })
+    }
+
+    /// Apply a function to each item in this channel, repeating while handlers request it.
+    ///
+    /// A handler may call [ReEmit::schedule] to have another pass over all items run after the
+    /// current one completes, instead of emitting into this channel recursively. Scheduling
+    /// more than once during a pass still results in a single extra pass. Terminating is left
+    /// to the handlers: a handler that schedules on every pass loops forever.
+    ///
+    /// Returns the number of passes made.
+    pub fn emit_requeuing(&self, mut handler: impl FnMut(&mut T, &ReEmit)) -> usize {
+        self.emission(1, || {
+            let mut passes = 0;
+            loop {
+                let reemit = ReEmit {
+                    scheduled: Cell::new(false),
+                };
+                for item in self.items.iter() {
+                    item.node.emit(|x| (handler)(x, &reemit));
+                }
+                passes += 1;
+
+                if !reemit.scheduled.get() {
+                    break passes;
+                }
+            }
+        })
+    }
+
+    /// Number of emissions on this channel currently in progress on the stack.
+    ///
+    /// Inside a handler this is at least 1; a value above 1 means this channel has been
+    /// emitted into recursively.
+    pub fn reentrancy_depth(&self) -> usize {
+        self.depth.get()
+    }
+
+    // Start an emission on this channel and run `body` inside the middleware. Returns
+    // `skipped` if the middleware never runs `body`.
+    fn emission<R>(&self, skipped: R, mut body: impl FnMut() -> R) -> R {
+        let _emission = self.enter();
+
+        if self.middleware.is_empty() {
+            return (body)();
+        }
+        let mut result = None;
+        chain(&self.middleware, &mut || result = Some((body)()));
+        result.unwrap_or(skipped)
+    }
+
+    // Start an emission on this channel, which ends when the returned value is dropped.
+    fn enter(&self) -> Emission<'_> {
+        #[cfg(feature = "log")]
+        log::trace!(
+            "revent: emit: channel of {} with {} nodes",
+            std::any::type_name::<T>(),
+            self.items.iter().count()
+        );
+        self.trace.log();
+        Trace::indent();
+        self.depth.set(self.depth.get() + 1);
+        Emission {
+            depth: &self.depth,
+            _cascade: Cascade::enter(),
+        }
+    }
+
+    // Apply `modifier` to every entry holding `item`, keeping the order of this channel.
+    fn modify(&mut self, item: &Node<T>, mut modifier: impl FnMut(&mut Entry<T>)) -> bool {
+        let mut found = false;
+        let entries = self
+            .items
+            .iter()
+            .cloned()
+            .map(|mut entry| {
+                if Node::ptr_eq(item, &entry.node) {
+                    (modifier)(&mut entry);
+                    found = true;
+                }
+                entry
+            })
+            .collect();
+
+        if found {
+            self.rebuild(entries);
+        }
+        found
+    }
+
+    // Get the insertion number for a node about to be inserted. Unlike generations, these are
+    // never reused.
+    fn next_insertion(&mut self) -> u64 {
+        self.insertions += 1;
+        self.insertions - 1
+    }
+
+    // Get the generation for a node about to be inserted. A node that was removed from this
+    // channel gets its original generation back unless that generation is in use again, any
+    // other node gets a new one.
+    fn generation_of(&mut self, item: &Node<T>) -> u64 {
+        self.retired.retain(|x| x.0.upgrade().is_some());
+        let retired = self
+            .retired
+            .iter()
+            .position(|x| matches!(x.0.upgrade(), Some(x) if Node::ptr_eq(item, &x)));
+
+        let generation = retired.map(|index| self.retired.remove(index).1);
+
+        match generation {
+            Some(generation) if !self.items.iter().any(|x| x.generation == generation) => {
+                generation
+            }
+            _ => {
+                self.generation += 1;
+                self.generation - 1
+            }
+        }
+    }
+
+    // Remove the entries matching `predicate`, remembering their generations. Only the most
+    // recently removed nodes are remembered, so inserting stays cheap after much churn.
+    fn retire(&mut self, predicate: impl Fn(&Entry<T>) -> bool) {
+        self.retired.retain(|x| x.0.upgrade().is_some());
+        for entry in self.items.iter().filter(|x| (predicate)(x)) {
+            if !self.retired.iter().any(|x| x.1 == entry.generation) {
+                self.retired
+                    .push((Node::downgrade(&entry.node), entry.generation));
+            }
+        }
+        if self.retired.len() > RETIRED_LIMIT {
+            let excess = self.retired.len() - RETIRED_LIMIT;
+            self.retired.drain(..excess);
+        }
+        self.items.retain(|x| !(predicate)(x));
+    }
+
+    // Split the entries into those holding `item` and the rest, both in emission order.
+    fn partition(&self, item: &Node<T>) -> (Vec<Entry<T>>, Vec<Entry<T>>) {
+        self.items
+            .iter()
+            .cloned()
+            .partition(|x| Node::ptr_eq(item, &x.node))
+    }
+
+    // Replace the contents of this channel such that entries sharing a `relative` value are
+    // emitted in the order given. Entries with a negative `relative` value are prepended by
+    // `insert`, so those are inserted in reverse.
+    fn rebuild(&mut self, entries: Vec<Entry<T>>) {
+        let (negative, positive): (Vec<_>, Vec<_>) =
+            entries.into_iter().partition(|x| x.relative < 0);
+
+        let mut items = IsizeVec::default();
+        for entry in negative.into_iter().rev() {
+            items.insert(entry.relative, entry);
+        }
+        for entry in positive {
+            items.insert(entry.relative, entry);
+        }
+        self.items = items;
+    }
+}
+
+/// Order in which [Channel::emit_ordered] visits items.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EmitOrder {
+    /// Visit items in emission order, like [Channel::emit].
+    Forward,
+    /// Visit items in reverse emission order.
+    Reverse,
+    /// Visit the items at the given indices of the emission order, in the order listed.
+    Custom(Vec<usize>),
+}
+
+/// Error returned by [Channel::try_insert] when the channel is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CapacityError {
+    /// Number of nodes in the channel.
+    pub current: usize,
+    /// Capacity limit of the channel.
+    pub max: usize,
+}
+
+impl fmt::Display for CapacityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "channel capacity limit exceeded: {} of {} nodes",
+            self.current, self.max
+        )
+    }
+}
+
+impl Error for CapacityError {}
+
+/// Emission with several options applied.
+///
+/// Created by [Channel::emit_builder]. Each option is a type parameter, so the resulting
+/// emission involves no dynamic dispatch.
+pub struct EmitBuilder<'a, T: ?Sized, F, O> {
+    channel: &'a Channel<T>,
+    filter: F,
+    order: Option<O>,
+    token: Option<&'a AtomicBool>,
+}
+
+impl<'a, T: ?Sized, F, O> EmitBuilder<'a, T, F, O> {
+    /// Only visit items for which `filter` returns `true`.
+    ///
+    /// The filter is evaluated right before an item would be visited.
+    pub fn filtered<G: FnMut(&T) -> bool>(self, filter: G) -> EmitBuilder<'a, T, G, O> {
+        EmitBuilder {
+            channel: self.channel,
+            filter,
+            order: self.order,
+            token: self.token,
+        }
+    }
+
+    /// Visit items in ascending order of the key computed by `order`.
+    ///
+    /// Items with equal keys keep their emission order. All keys are computed before any item
+    /// is visited.
+    pub fn ordered_by<P, K>(self, order: P) -> EmitBuilder<'a, T, F, P>
+    where
+        P: FnMut(&T) -> K,
+        K: Ord,
+    {
+        EmitBuilder {
+            channel: self.channel,
+            filter: self.filter,
+            order: Some(order),
+            token: self.token,
+        }
+    }
+
+    /// Stop visiting items once `token` is set.
+    ///
+    /// See [Channel::emit_cancellable].
+    pub fn cancellable(mut self, token: &'a AtomicBool) -> Self {
+        self.token = Some(token);
+        self
+    }
+
+    /// Perform the emission, returning `false` if it was cancelled.
+    pub fn run<K: Ord>(self, mut handler: impl FnMut(&mut T)) -> bool
+    where
+        F: FnMut(&T) -> bool,
+        O: FnMut(&T) -> K,
+    {
+        let EmitBuilder {
+            channel,
+            mut filter,
+            mut order,
+            token,
+        } = self;
+
+        channel.emission(true, || {
+            let mut items = channel.items.iter().collect::<Vec<_>>();
+            if let Some(order) = order.as_mut() {
+                let mut keyed = items
+                    .drain(..)
+                    .map(|x| (x.node.emit(|y| (order)(y)), x))
+                    .collect::<Vec<_>>();
+                keyed.sort_by(|a, b| a.0.cmp(&b.0));
+                items.extend(keyed.into_iter().map(|x| x.1));
+            }
+
+            let mut completed = true;
+            for item in items {
+                if let Some(token) = token {
+                    if token.load(Ordering::Acquire) {
+                        completed = false;
+                        break;
+                    }
+                }
+                let filter = &mut filter;
+                item.node.emit(|x| {
+                    if (filter)(x) {
+                        (handler)(x);
+                    }
+                });
+            }
+            completed
+        })
+    }
+}
+
+/// Stepwise emission over a [Channel].
+///
+/// Created by [Channel::emit_stepwise].
+pub struct EmitStepper<'a, T: ?Sized> {
+    channel: &'a Channel<T>,
+    items: Vec<&'a Node<T>>,
+    next: usize,
+}
+
+impl<'a, T: ?Sized> EmitStepper<'a, T> {
+    /// Apply a function to the next item, returning whether any items remain afterwards.
+    ///
+    /// Each step counts as an emission on the channel, so it is traced and included in
+    /// [reentrancy_depth](Channel::reentrancy_depth). Does nothing if all items have been
+    /// visited.
+    pub fn next(&mut self, handler: impl FnOnce(&mut T)) -> bool {
+        if let Some(item) = self.items.get(self.next) {
+            let mut handler = Some(handler);
+            self.channel.emission((), || {
+                if let Some(handler) = handler.take() {
+                    item.emit(handler);
+                }
+            });
+            self.next += 1;
+        }
+        self.next < self.items.len()
+    }
+}
+
+/// Token for requesting another pass from [Channel::emit_requeuing].
+pub struct ReEmit {
+    scheduled: Cell<bool>,
+}
+
+impl ReEmit {
+    /// Run another pass over all items once the current pass completes.
+    pub fn schedule(&self) {
+        self.scheduled.set(true);
+    }
+}
+
+/// Emit to two channels of different types as one logical event.
+///
+/// Every item in `a` is visited with `handler_a` before any item in `b` is visited with
+/// `handler_b`. Actions [defer](crate::defer)red by either run after both channels.
+pub fn dual_emit<A: ?Sized, B: ?Sized>(
+    a: &Channel<A>,
+    b: &Channel<B>,
+    handler_a: impl FnMut(&mut A),
+    handler_b: impl FnMut(&mut B),
+) {
+    let _cascade = Cascade::enter();
+    a.emit(handler_a);
+    b.emit(handler_b);
+}
+
+struct Entry<T: ?Sized> {
+    relative: isize,
+    generation: u64,
+    insertion: u64,
+    #[cfg(feature = "timestamps")]
+    inserted: Instant,
+    tags: Vec<String>,
+    node: Node<T>,
+}
+
+impl<T: ?Sized> Clone for Entry<T> {
+    fn clone(&self) -> Self {
+        Self {
+            relative: self.relative,
+            generation: self.generation,
+            insertion: self.insertion,
+            #[cfg(feature = "timestamps")]
+            inserted: self.inserted,
+            tags: self.tags.clone(),
+            node: self.node.clone(),
+        }
+    }
+}
+
+// Number of removed nodes a channel remembers the generation of.
+const RETIRED_LIMIT: usize = 64;
+
+type Middleware = Box<dyn Fn(&mut dyn FnMut())>;
+type PanicObserver = Box<dyn FnMut(usize)>;
+type Replay<T> = Box<dyn Fn(&mut T)>;
+type PlainEmitBuilder<'a, T> = EmitBuilder<'a, T, fn(&T) -> bool, fn(&T)>;
+
+// Run `core` inside each middleware, the first being outermost.
+fn chain(middleware: &[Middleware], core: &mut dyn FnMut()) {
+    match middleware.split_first() {
+        Some((first, rest)) => (first)(&mut || chain(rest, core)),
+        None => (core)(),
+    }
+}
+
+struct Emission<'a> {
+    depth: &'a Cell<usize>,
+    _cascade: Cascade,
+}
+
+impl<'a> Drop for Emission<'a> {
+    fn drop(&mut self) {
+        self.depth.set(self.depth.get() - 1);
+        Trace::dedent();
+    }
+}
+
+struct PanicGuard<'a> {
+    observer: &'a RefCell<PanicObserver>,
+    index: usize,
+    panicking: bool,
+}
+
+impl<'a> Drop for PanicGuard<'a> {
+    fn drop(&mut self) {
+        if !self.panicking && thread::panicking() {
+            if let Ok(mut observer) = self.observer.try_borrow_mut() {
+                (observer)(self.index);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Channel, Node};
+    use crate::{
+        is_context_active,
+        testing::{numbered, visited},
+    };
+
+    #[test]
+    fn removing_considers_order() {
+        let mut channel = Channel::new();
+        let node = Node::new(());
+        channel.insert(0, node.clone());
+        channel.remove(&node);
+
+        channel.insert(1, Node::new(()));
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn inserting_appends_or_prepends(relative: isize, nodes: usize) {
+        let mut channel = Channel::new();
+
+        for node in 0..nodes {
+            channel.insert(relative, Node::new(node));
+        }
+
+        if relative >= 0 {
+            let mut value = 0;
+            channel.emit(|x| {
+                assert_eq!(value, *x);
+                value += 1;
+            });
+            assert_eq!(value, nodes);
+        } else {
+            let mut value = nodes;
+            channel.emit(|x| {
+                value -= 1;
+                assert_eq!(value, *x);
+            });
+            assert_eq!(value, 0);
+        }
+    }
+
+    #[test]
+    fn basic() {
+        let mut channel = Channel::new();
+
+        let node = Node::new(0);
+        channel.insert(0, node.clone());
+        channel.insert(1, Node::new(1));
+
+        let mut number = 0;
+        channel.emit(|x| {
+            assert_eq!(*x, number);
+            number += 1;
+        });
+        assert_eq!(number, 2);
+
+        channel.remove(&node);
+
+        let mut number = 1;
+        channel.emit(|x| {
+            assert_eq!(*x, number);
+            number += 1;
+        });
+        assert_eq!(number, 2);
+    }
+
+    #[test]
+    fn haystack() {
+        let mut channel = Channel::new();
+
+        let node = Node::new(0);
+        for _ in 0..10 {
+            channel.insert(0, node.clone());
+        }
+        for _ in 0..10 {
+            channel.insert(2, node.clone());
+        }
+
+        channel.insert(1, Node::new(1));
+
+        channel.remove(&node);
+
+        let mut count = 0;
+        channel.emit(|x| {
+            assert_eq!(*x, 1);
+            count += 1;
+        });
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "revent: insert: channel capacity limit exceeded")]
+    fn capacity_limit() {
+        let mut channel = Channel::new();
+        channel.set_capacity_limit(2);
+
+        channel.insert(0, Node::new(()));
+        channel.insert(0, Node::new(()));
+        channel.insert(0, Node::new(()));
+    }
+
+    #[test]
+    fn try_insert_full() {
+        use super::CapacityError;
+
+        let mut channel = Channel::new();
+        channel.set_capacity_limit(1);
+
+        assert_eq!(channel.try_insert(0, Node::new(())), Ok(()));
+        assert_eq!(
+            channel.try_insert(0, Node::new(())),
+            Err(CapacityError { current: 1, max: 1 })
+        );
+    }
+
+    #[test]
+    fn append() {
+        let mut channel = Channel::new();
+        channel.insert(-1, Node::new(0));
+        channel.insert(1, Node::new(1));
+
+        let mut other = Channel::new();
+        other.insert(1, Node::new(2));
+        other.insert(-1, Node::new(3));
+        other.insert(-1, Node::new(4));
+        other.insert(0, Node::new(5));
+
+        channel.append(&mut other);
+        assert_eq!(visited(&channel), [0, 4, 3, 5, 1, 2]);
+        assert!(!other.emit_any(|_| {}));
+
+        let mut order = Vec::new();
+        channel.emit_by_generation(|x| order.push(*x));
+        assert_eq!(order, [0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn replace_keeps_position() {
+        let mut channel = Channel::new();
+        let old = Node::new(1);
+        channel.insert(0, Node::new(0));
+        channel.insert(0, old.clone());
+        channel.insert(0, Node::new(2));
+
+        assert!(channel.replace(&old, Node::new(10)));
+        assert!(!channel.replace(&old, Node::new(20)));
+
+        let order = visited(&channel);
+        assert_eq!(order, [0, 10, 2]);
+    }
+
+    #[test]
+    fn move_to_front_and_back() {
+        let mut channel = Channel::new();
+        let nodes = (0..5).map(Node::new).collect::<Vec<_>>();
+        channel.insert(-1, nodes[0].clone());
+        channel.insert(-1, nodes[1].clone());
+        channel.insert(0, nodes[2].clone());
+        channel.insert(0, nodes[3].clone());
+        channel.insert(1, nodes[4].clone());
+
+        assert_eq!(visited(&channel), [1, 0, 2, 3, 4]);
+
+        assert!(channel.move_to_front(&nodes[3]));
+        assert_eq!(visited(&channel), [3, 1, 0, 2, 4]);
+
+        assert!(channel.move_to_back(&nodes[1]));
+        assert_eq!(visited(&channel), [3, 0, 2, 4, 1]);
+
+        channel.remove(&nodes[2]);
+        assert!(!channel.move_to_front(&nodes[2]));
+        assert_eq!(visited(&channel), [3, 0, 4, 1]);
+    }
+
+    #[test]
+    fn weak_handles() {
+        let mut channel = Channel::new();
+        let node = Node::new(0);
+        channel.insert(0, node.clone());
+        channel.insert(0, Node::new(1));
+
+        let handles = channel.weak_handles();
+        assert_eq!(handles.len(), 2);
+
+        channel.remove(&node);
+        drop(node);
+
+        assert!(handles[0].upgrade().is_none());
+        handles[1].upgrade().unwrap().emit(|x| assert_eq!(*x, 1));
+    }
+
+    #[test]
+    fn middleware_order() {
+        use std::{cell::RefCell, rc::Rc};
+
+        let mut channel = numbered(2);
+        let log = Rc::new(RefCell::new(Vec::new()));
+
+        for name in &["outer", "inner"] {
+            let log = log.clone();
+            channel.add_middleware(move |next| {
+                log.borrow_mut().push(format!("{} before", name));
+                next();
+                log.borrow_mut().push(format!("{} after", name));
+            });
+        }
+
+        let inner = log.clone();
+        channel.emit(move |x| inner.borrow_mut().push(x.to_string()));
+        assert_eq!(
+            *log.borrow(),
+            [
+                "outer before",
+                "inner before",
+                "0",
+                "1",
+                "inner after",
+                "outer after"
+            ]
+        );
+    }
+
+    #[test]
+    fn middleware_skips_emission() {
+        let mut channel = numbered(2);
+        channel.add_middleware(|_| {});
+
+        let mut count = 0;
+        channel.emit(|_| count += 1);
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn subscriber_names() {
+        use std::fmt::Debug;
+
+        let mut channel = Channel::<dyn Debug>::new();
+        channel.insert(0, Node::new(0u8));
+        channel.insert(0, Node::new(String::new()));
+        channel.insert(-1, Node::new(0i32));
+
+        assert_eq!(
+            channel.subscriber_names(),
+            ["i32", "u8", "alloc::string::String"]
+        );
+    }
+
+    #[test]
+    fn middleware_wraps_variants() {
+        use std::{cell::Cell, rc::Rc};
+
+        let mut channel = numbered(2);
+        let skip = Rc::new(Cell::new(false));
+        let runs = Rc::new(Cell::new(0));
+
+        let (skipping, counter) = (skip.clone(), runs.clone());
+        channel.add_middleware(move |next| {
+            counter.set(counter.get() + 1);
+            if !skipping.get() {
+                next();
+            }
+        });
+
+        assert!(channel.emit_any(|_| {}));
+        assert_eq!(channel.try_emit(|x| Err::<(), _>(*x)), Err(0));
+        assert_eq!(channel.emit_take(5, |_| {}), 2);
+        channel.emit_stepwise().next(|_| {});
+        assert_eq!(runs.get(), 4);
+
+        skip.set(true);
+        assert!(!channel.emit_any(|_| unreachable!()));
+        assert_eq!(channel.try_emit(|x| Err(*x)), Ok(()));
+        assert!(channel.emit_mask(|_| unreachable!()).is_empty());
+        assert!(channel.emit_builder().run(|_| unreachable!()));
+        assert_eq!(runs.get(), 8);
+    }
+
+    #[test]
+    fn emit_find_fallback() {
+        let mut channel = numbered(3);
+
+        let mut seen = Vec::new();
+        assert!(channel.emit_find(|x| {
+            seen.push(*x);
+            *x == 1
+        }));
+        assert_eq!(seen, [0, 1]);
+
+        assert!(!channel.emit_find(|_| false));
+
+        channel.set_fallback(Node::new(9));
+        let mut seen = Vec::new();
+        assert!(channel.emit_find(|x| {
+            seen.push(*x);
+            *x == 9
+        }));
+        assert_eq!(seen, [0, 1, 2, 9]);
+        assert_eq!(visited(&channel), [0, 1, 2]);
+    }
+
+    #[test]
+    fn emit_assert_complete() {
+        let channel = numbered(3);
+        channel.emit_assert_complete(|x| *x += 1);
+        assert_eq!(visited(&channel), [1, 2, 3]);
+    }
+
+    #[test]
+    #[should_panic(expected = "revent: emit_assert_complete: handler ran 0 times for 2 nodes")]
+    fn emit_assert_complete_skipped() {
+        let mut channel = numbered(2);
+        channel.add_middleware(|_| {});
+        channel.emit_assert_complete(|_| {});
+    }
+
+    #[test]
+    fn select() {
+        let channel = numbered(5);
+
+        let selected = channel.select(|x| {
+            assert!(!is_context_active());
+            x % 2 == 0
+        });
+        assert_eq!(selected.len(), 3);
+
+        for node in &selected {
+            node.emit(|x| *x += 10);
+        }
+        assert_eq!(visited(&channel), [10, 1, 12, 3, 14]);
+    }
+
+    #[test]
+    fn emit_except_skips_self() {
+        let mut channel = Channel::new();
+        let node = Node::new(0);
+        channel.insert(0, node.clone());
+        channel.insert(0, Node::new(0));
+
+        node.emit(|_| {
+            channel.emit_except(&node, |x| *x += 1);
+        });
+
+        let values = visited(&channel);
+        assert_eq!(values, [0, 1]);
     }
 
-    /// Remove all occurrences of a node from this channel.
-    ///
-    /// # Performance #
-    ///
-    /// Performs a linear scan and retains only those nodes that do not match.
-    pub fn remove(&mut self, item: &Node<T>) {
-        self.items.retain(|x| !Node::<T>::ptr_eq(item, x));
+    #[test]
+    fn emit_builder() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let channel = numbered(10);
+
+        let mut order = Vec::new();
+        assert!(channel.emit_builder().run(|x| order.push(*x)));
+        assert_eq!(order, visited(&channel));
+
+        let mut order = Vec::new();
+        let token = AtomicBool::new(false);
+        let completed = channel
+            .emit_builder()
+            .filtered(|x| x % 3 != 0)
+            .ordered_by(|x| std::cmp::Reverse(*x))
+            .cancellable(&token)
+            .run(|x| {
+                order.push(*x);
+                if *x == 4 {
+                    token.store(true, Ordering::Release);
+                }
+            });
+        assert!(!completed);
+        assert_eq!(order, [8, 7, 5, 4]);
     }
 
-    /// Apply a function to each item in this channel.
-    pub fn emit(&self, mut handler: impl FnMut(&mut T)) {
-        self.trace.log();
-        Trace::indent();
+    #[test]
+    fn reentrancy_depth() {
+        use crate::Suspend;
 
-        for item in self.items.iter() {
-            item.emit(|x| {
-                (handler)(x);
+        let mut channel = Channel::new();
+        channel.insert(0, Node::new(2));
+        assert_eq!(channel.reentrancy_depth(), 0);
+
+        fn recurse(channel: &Channel<i32>, depths: &mut Vec<usize>) {
+            channel.emit(|x| {
+                depths.push(channel.reentrancy_depth());
+                if *x > 0 {
+                    *x -= 1;
+                    x.suspend(|| recurse(channel, depths));
+                }
             });
         }
 
-        Trace::dedent();
+        let mut depths = Vec::new();
+        recurse(&channel, &mut depths);
+        assert_eq!(depths, [1, 2, 3]);
+        assert_eq!(channel.reentrancy_depth(), 0);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::{Channel, Node};
+    #[test]
+    fn emit_stepwise() {
+        let channel = numbered(3);
+
+        let mut stepper = channel.emit_stepwise();
+        let mut visited = Vec::new();
+        assert!(stepper.next(|x| visited.push(*x)));
+        assert!(stepper.next(|x| visited.push(*x)));
+        assert!(!stepper.next(|x| visited.push(*x)));
+        assert!(!stepper.next(|_| unreachable!()));
+        assert_eq!(visited, [0, 1, 2]);
+    }
 
     #[test]
-    fn removing_considers_order() {
-        let mut channel = Channel::new();
-        let node = Node::new(());
-        channel.insert(0, node.clone());
-        channel.remove(&node);
+    fn emit_stepwise_counts_as_emission() {
+        let channel = numbered(2);
 
-        channel.insert(1, Node::new(()));
+        let mut stepper = channel.emit_stepwise();
+        let mut depths = Vec::new();
+        stepper.next(|_| depths.push(channel.reentrancy_depth()));
+        depths.push(channel.reentrancy_depth());
+        stepper.next(|_| depths.push(channel.reentrancy_depth()));
+        assert_eq!(depths, [1, 0, 1]);
     }
 
     #[quickcheck_macros::quickcheck]
-    fn inserting_appends_or_prepends(relative: isize, nodes: usize) {
-        let mut channel = Channel::new();
+    fn emit_collect_visits_all(nodes: u8) {
+        let channel = numbered(usize::from(nodes));
 
-        for node in 0..nodes {
-            channel.insert(relative, Node::new(node));
-        }
+        let mut count = 0;
+        let errors = channel.emit_collect(|x| {
+            count += 1;
+            if *x % 2 == 0 {
+                Err(*x)
+            } else {
+                Ok(())
+            }
+        });
 
-        if relative >= 0 {
-            let mut value = 0;
-            channel.emit(|x| {
-                assert_eq!(value, *x);
-                value += 1;
-            });
-            assert_eq!(value, nodes);
-        } else {
-            let mut value = nodes;
-            channel.emit(|x| {
-                value -= 1;
-                assert_eq!(value, *x);
-            });
-            assert_eq!(value, 0);
+        assert_eq!(count, usize::from(nodes));
+        assert_eq!(
+            errors,
+            (0..usize::from(nodes)).step_by(2).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn emit_two_phase() {
+        let channel = numbered(5);
+
+        let result = channel.emit_two_phase(|x| if *x < 3 { Ok(()) } else { Err(*x) }, |x| *x = 0);
+        assert_eq!(result, Err(3));
+        let mut sum = 0;
+        channel.emit(|x| sum += *x);
+        assert_eq!(sum, 10);
+
+        let result = channel.emit_two_phase(|_| Ok::<_, ()>(()), |x| *x = 0);
+        assert_eq!(result, Ok(()));
+        let mut sum = 0;
+        channel.emit(|x| sum += *x);
+        assert_eq!(sum, 0);
+    }
+
+    #[test]
+    fn emit_mask() {
+        let channel = numbered(5);
+        assert_eq!(
+            channel.emit_mask(|x| *x % 2 == 0),
+            [true, false, true, false, true]
+        );
+    }
+
+    #[test]
+    fn emit_into_truncates() {
+        let channel = numbered(5);
+
+        let mut out = [0; 3];
+        let mut count = 0;
+        assert_eq!(
+            channel.emit_into(&mut out, |x| {
+                count += 1;
+                *x * 2
+            }),
+            3
+        );
+        assert_eq!(out, [0, 2, 4]);
+        assert_eq!(count, 3);
+
+        let mut out = [0; 8];
+        assert_eq!(channel.emit_into(&mut out, |x| *x), 5);
+        assert_eq!(out[..5], [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn emit_cancellable() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let channel = numbered(10);
+
+        let token = AtomicBool::new(false);
+        let mut count = 0;
+        assert!(channel.emit_cancellable(&token, |_| count += 1));
+        assert_eq!(count, 10);
+
+        let mut count = 0;
+        assert!(!channel.emit_cancellable(&token, |x| {
+            count += 1;
+            if *x == 4 {
+                token.store(true, Ordering::Release);
+            }
+        }));
+        assert_eq!(count, 5);
+    }
+
+    #[test]
+    fn replay() {
+        let mut channel = numbered(1);
+        channel.set_replay(2);
+
+        for value in 1..4 {
+            channel.emit_value(value, |x, value| *x += value);
         }
+        assert_eq!(visited(&channel), [6]);
+
+        channel.insert(0, Node::new(0));
+        assert_eq!(visited(&channel), [6, 5]);
+
+        channel.set_replay(1);
+        channel.insert(0, Node::new(0));
+        assert_eq!(visited(&channel), [6, 5, 3]);
     }
 
     #[test]
-    fn basic() {
+    fn emit_any() {
         let mut channel = Channel::new();
+        assert!(!channel.emit_any(|_| {}));
 
-        let node = Node::new(0);
-        channel.insert(0, node.clone());
-        channel.insert(1, Node::new(1));
-
-        let mut number = 0;
-        channel.emit(|x| {
-            assert_eq!(*x, number);
-            number += 1;
-        });
-        assert_eq!(number, 2);
+        channel.insert(0, Node::new(()));
+        assert!(channel.emit_any(|_| {}));
+    }
 
-        channel.remove(&node);
+    #[quickcheck_macros::quickcheck]
+    fn emit_retaining(nodes: u8) {
+        let mut channel = numbered(usize::from(nodes));
 
-        let mut number = 1;
-        channel.emit(|x| {
-            assert_eq!(*x, number);
-            number += 1;
+        let mut count = 0;
+        channel.emit_retaining(|x| {
+            count += 1;
+            *x % 3 != 0
         });
-        assert_eq!(number, 2);
+        assert_eq!(count, usize::from(nodes));
+
+        let remaining = visited(&channel);
+        assert_eq!(
+            remaining,
+            (0..usize::from(nodes))
+                .filter(|x| x % 3 != 0)
+                .collect::<Vec<_>>()
+        );
     }
 
     #[test]
-    fn haystack() {
+    fn emit_by_generation() {
         let mut channel = Channel::new();
+        let nodes = (0..4).map(Node::new).collect::<Vec<_>>();
+        channel.insert(2, nodes[0].clone());
+        channel.insert(1, nodes[1].clone());
+        channel.insert(0, nodes[2].clone());
+        channel.insert(-1, nodes[3].clone());
 
-        let node = Node::new(0);
-        for _ in 0..10 {
+        channel.move_to_back(&nodes[3]);
+        channel.remove(&nodes[1]);
+        channel.insert(0, nodes[1].clone());
+
+        let mut order = Vec::new();
+        channel.emit_by_generation(|x| order.push(*x));
+        assert_eq!(order, [0, 1, 2, 3]);
+
+        channel.remove(&nodes[0]);
+        channel.insert(0, Node::new(4));
+        channel.insert(0, nodes[0].clone());
+
+        let mut order = Vec::new();
+        channel.emit_by_generation(|x| order.push(*x));
+        assert_eq!(order, [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn emit_by_generation_forgets_old_removals() {
+        for &(churn, expected) in &[(63, [0, 1]), (64, [1, 0])] {
+            let mut channel = Channel::new();
+            let first = Node::new(0);
+            channel.insert(0, first.clone());
+            channel.insert(0, Node::new(1));
+            channel.remove(&first);
+
+            let others = (0..churn).map(|x| Node::new(x + 2)).collect::<Vec<_>>();
+            for other in &others {
+                channel.insert(0, other.clone());
+                channel.remove(other);
+            }
+            channel.insert(0, first);
+
+            let mut order = Vec::new();
+            channel.emit_by_generation(|x| order.push(*x));
+            assert_eq!(order, expected);
+        }
+    }
+
+    #[test]
+    fn emit_ordered() {
+        use super::EmitOrder;
+
+        let channel = numbered(4);
+
+        let order = |emit_order| {
+            let mut order = Vec::new();
+            channel.emit_ordered(emit_order, |x| order.push(*x));
+            order
+        };
+        assert_eq!(order(EmitOrder::Forward), [0, 1, 2, 3]);
+        assert_eq!(order(EmitOrder::Reverse), [3, 2, 1, 0]);
+        assert_eq!(order(EmitOrder::Custom(vec![2, 0, 2])), [2, 0, 2]);
+    }
+
+    #[test]
+    #[should_panic(expected = "revent: emit_ordered: index out of bounds")]
+    fn emit_ordered_out_of_bounds() {
+        use super::EmitOrder;
+
+        numbered(4).emit_ordered(EmitOrder::Custom(vec![0, 4]), |_| {});
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn emit_shuffled(nodes: u8, seed: u64) {
+        let channel = numbered(usize::from(nodes));
+
+        let shuffle = || {
+            let mut order = Vec::new();
+            channel.emit_shuffled(seed, |x| order.push(*x));
+            order
+        };
+        let order = shuffle();
+        assert_eq!(order, shuffle());
+
+        let mut sorted = order.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, visited(&channel));
+    }
+
+    #[test]
+    fn emit_group_by() {
+        let channel = numbered(10);
+
+        let mut visited = Vec::new();
+        channel.emit_group_by(1, |x| *x % 3, |x| visited.push(*x));
+        assert_eq!(visited, [1, 4, 7]);
+    }
+
+    #[test]
+    fn emit_tagged() {
+        let mut channel = Channel::new();
+        let nodes = (0..4).map(Node::new).collect::<Vec<_>>();
+        for node in &nodes {
             channel.insert(0, node.clone());
         }
-        for _ in 0..10 {
-            channel.insert(2, node.clone());
+
+        assert!(channel.add_tag(&nodes[1], "odd"));
+        assert!(channel.add_tag(&nodes[3], "odd"));
+        assert!(channel.add_tag(&nodes[3], "last"));
+
+        let mut visited = Vec::new();
+        channel.emit_tagged("odd", |x| visited.push(*x));
+        assert_eq!(visited, [1, 3]);
+
+        channel.remove(&nodes[3]);
+        assert!(!channel.add_tag(&nodes[3], "odd"));
+        channel.emit_tagged("last", |_| unreachable!());
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn emit_state_sums(values: Vec<u8>) {
+        let mut channel = Channel::new();
+        for value in &values {
+            channel.insert(0, Node::new(usize::from(*value)));
         }
 
-        channel.insert(1, Node::new(1));
+        let mut sum = 0;
+        channel.emit_state(&mut sum, |sum, x| *sum += *x);
+        assert_eq!(sum, values.iter().map(|x| usize::from(*x)).sum::<usize>());
+    }
 
-        channel.remove(&node);
+    #[test]
+    fn emit_continue_on_panic() {
+        let channel = numbered(5);
 
         let mut count = 0;
-        channel.emit(|x| {
-            assert_eq!(*x, 1);
+        let payload = channel.emit_continue_on_panic(|x| {
             count += 1;
+            if *x % 2 == 1 {
+                panic!("{}", x);
+            }
         });
-        assert_eq!(count, 1);
+        assert_eq!(count, 5);
+        assert_eq!(payload.unwrap().downcast_ref::<String>().unwrap(), "1");
+
+        assert!(channel.emit_continue_on_panic(|_| {}).is_none());
+    }
+
+    #[test]
+    fn dual_emit_orders_a_before_b() {
+        use crate::dual_emit;
+
+        let mut a = Channel::new();
+        let mut b = Channel::new();
+        a.insert(0, Node::new('a'));
+        b.insert(0, Node::new(String::from("b")));
+
+        let order = std::cell::RefCell::new(String::new());
+        dual_emit(
+            &a,
+            &b,
+            |x| order.borrow_mut().push(*x),
+            |x| order.borrow_mut().push_str(x),
+        );
+        assert_eq!(*order.borrow(), "ab");
+    }
+
+    #[test]
+    fn emit_prune_panicking() {
+        let mut channel = numbered(6);
+
+        assert_eq!(
+            channel.emit_prune_panicking(|x| {
+                if *x % 2 == 0 {
+                    panic!();
+                }
+            }),
+            3
+        );
+
+        let remaining = visited(&channel);
+        assert_eq!(remaining, [1, 3, 5]);
+    }
+
+    #[test]
+    fn emit_last_is_newest() {
+        let mut channel = Channel::new();
+        assert!(!channel.emit_last(|_| unreachable!()));
+
+        channel.insert(1, Node::new(0));
+        channel.insert(0, Node::new(1));
+
+        let mut last = None;
+        assert!(channel.emit_last(|x| last = Some(*x)));
+        assert_eq!(last, Some(1));
+    }
+
+    #[test]
+    fn emit_last_after_reinsert() {
+        let mut channel = Channel::new();
+        let first = Node::new(0);
+        channel.insert(0, first.clone());
+        channel.insert(0, Node::new(1));
+        channel.remove(&first);
+        channel.insert(0, first);
+
+        let mut last = None;
+        assert!(channel.emit_last(|x| last = Some(*x)));
+        assert_eq!(last, Some(0));
+    }
+
+    #[test]
+    fn emit_take() {
+        let channel = numbered(3);
+
+        assert_eq!(channel.emit_take(2, |x| *x += 10), 2);
+        assert_eq!(visited(&channel), [10, 11, 2]);
+
+        assert_eq!(channel.emit_take(5, |x| *x += 10), 3);
+        assert_eq!(visited(&channel), [20, 21, 12]);
+    }
+
+    #[test]
+    fn emit_scan() {
+        let channel = numbered(4);
+        assert_eq!(
+            channel.emit_scan(100, |sum, x| sum + *x),
+            [100, 101, 103, 106]
+        );
+
+        let empty = numbered(0);
+        assert!(empty.emit_scan(0, |_, _| unreachable!()).is_empty());
+    }
+
+    #[test]
+    fn try_emit_stops_at_error() {
+        let channel = numbered(4);
+
+        let result = channel.try_emit(|x| {
+            if *x == 2 {
+                return Err(*x);
+            }
+            *x += 10;
+            Ok(())
+        });
+        assert_eq!(result, Err(2));
+        assert_eq!(visited(&channel), [10, 11, 2, 3]);
+    }
+
+    #[test]
+    fn emit_requeuing() {
+        let channel = numbered(3);
+
+        let passes = channel.emit_requeuing(|x, reemit| {
+            *x += 1;
+            if *x < 4 {
+                reemit.schedule();
+            }
+        });
+        assert_eq!(passes, 4);
+        assert_eq!(visited(&channel), [4, 5, 6]);
+    }
+
+    #[test]
+    fn set_nodes() {
+        let mut channel = Channel::new();
+        let kept = Node::new(0);
+        channel.insert(0, kept.clone());
+        channel.insert(0, Node::new(1));
+
+        channel.set_nodes(vec![Node::new(7), kept, Node::new(8)]);
+        assert_eq!(visited(&channel), [7, 0, 8]);
+
+        channel.set_nodes(Vec::new());
+        assert!(!channel.emit_any(|_| {}));
+    }
+
+    #[test]
+    fn panic_observer() {
+        use std::{cell::Cell, panic, rc::Rc};
+
+        let mut channel = numbered(5);
+
+        let observed = Rc::new(Cell::new(None));
+        let capture = observed.clone();
+        channel.set_panic_observer(move |index| capture.set(Some(index)));
+
+        channel.emit(|_| {});
+        assert_eq!(observed.get(), None);
+
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            channel.emit(|x| {
+                if *x == 3 {
+                    panic!();
+                }
+            });
+        }));
+        assert!(result.is_err());
+        assert_eq!(observed.get(), Some(3));
+    }
+}
+
+#[cfg(all(test, feature = "profiling"))]
+mod profiling_tests {
+    use crate::*;
+    use std::{thread, time::Duration};
+
+    #[test]
+    fn emit_timed() {
+        let mut channel = Channel::new();
+        channel.insert(0, Node::new(Duration::from_millis(0)));
+        channel.insert(0, Node::new(Duration::from_millis(10)));
+
+        let durations = channel.emit_timed(|x| thread::sleep(*x));
+        assert_eq!(durations.len(), 2);
+        assert!(durations[1] >= Duration::from_millis(10));
+    }
+}
+
+#[cfg(all(test, feature = "sampling"))]
+mod sampling_tests {
+    use crate::*;
+
+    #[quickcheck_macros::quickcheck]
+    fn emit_sample(nodes: u8, seed: u64) {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let mut channel = Channel::new();
+        for node in 0..nodes {
+            channel.insert(0, Node::new(node));
+        }
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut visited = Vec::new();
+        channel.emit_sample(0.5, &mut rng, |x| visited.push(*x));
+
+        assert_eq!(visited.len(), (f64::from(nodes) * 0.5).round() as usize);
+        assert!(visited.windows(2).all(|x| x[0] < x[1]));
+    }
+}
+
+#[cfg(all(test, feature = "timestamps"))]
+mod timestamps_tests {
+    use crate::*;
+
+    #[test]
+    fn oldest() {
+        let mut channel = Channel::new();
+        assert!(channel.oldest().is_none());
+
+        let first = Node::new(0);
+        channel.insert(1, first.clone());
+        channel.insert(0, Node::new(1));
+
+        assert!(Node::ptr_eq(&channel.oldest().unwrap(), &first));
+        assert!(channel.inserted_at(&first).is_some());
+
+        channel.remove(&first);
+        assert!(channel.inserted_at(&first).is_none());
+        channel.oldest().unwrap().emit(|x| assert_eq!(*x, 1));
     }
 }
 
@@ -63,17 +63,26 @@
 #![feature(coerce_unsized, drain_filter, unsize)]
 
 use self::trace::Trace;
-pub use self::{channel::Channel, node::Node, slot::Slot};
+pub use self::{
+    channel::{dual_emit, CapacityError, Channel, EmitBuilder, EmitOrder, EmitStepper, ReEmit},
+    node::{Node, NodeRef, WeakNode},
+    pipeline::Pipeline,
+    slot::Slot,
+    slot_map::SlotMap,
+};
 use std::{
-    cell::{Cell, UnsafeCell},
-    mem,
+    cell::{Cell, RefCell, UnsafeCell},
+    collections::VecDeque,
+    mem, process, thread,
 };
 
 #[cfg(feature = "asynchronous")]
 pub mod asynchronous;
 mod channel;
 mod node;
+mod pipeline;
 mod slot;
+mod slot_map;
 mod trace;
 
 // ---
@@ -93,6 +102,21 @@ fn is_borrowed(value: &Cell<BorrowFlag>) -> bool {
     value.get() != 0
 }
 
+#[inline(always)]
+fn borrow(value: &Cell<BorrowFlag>) {
+    value.set(value.get() + 1);
+}
+
+#[inline(always)]
+fn unborrow(value: &Cell<BorrowFlag>) {
+    value.set(value.get() - 1);
+}
+
+#[inline(always)]
+fn is_borrowed_mut(value: &Cell<BorrowFlag>) -> bool {
+    value.get() < 0
+}
+
 type BorrowFlag = isize;
 
 // ---
@@ -102,6 +126,28 @@ thread_local! {
     // being invoked on a `Node`. It is inside an `UnsafeCell` because it is only ever
     // pushed/popped in the same function, and we can prove that borrows are not propagated.
     static STACK: UnsafeCell<Vec<(*const Cell<BorrowFlag>, *mut (), usize)>> = UnsafeCell::new(Vec::new());
+
+    // Emissions on nodes and channels currently in progress. `Node` caches a pointer to this,
+    // like it does for `STACK`.
+    static CASCADE: Cascading = Cascading {
+        depth: Cell::new(0),
+        deferred: RefCell::new(VecDeque::new()),
+    };
+}
+
+pub(crate) struct Cascading {
+    // Number of emissions in progress.
+    depth: Cell<usize>,
+    // Actions queued by `defer`, run once the outermost emission returns.
+    deferred: RefCell<VecDeque<Box<dyn FnOnce()>>>,
+}
+
+// Get a pointer to the cascade state of this thread, to be used with `Cascade::enter_cached`.
+pub(crate) fn cascading() -> *const Cascading {
+    CASCADE.with(|x| {
+        let state: *const Cascading = x;
+        state
+    })
 }
 
 // ---
@@ -118,6 +164,11 @@ pub trait Suspend {
     /// Panics if the suspended object is not stored in a [Node], or if the object is not at the
     /// top of the current node stack.
     ///
+    /// # Aborts #
+    ///
+    /// Aborts the process if a [NodeRef] to the object acquired inside `runner` is still alive
+    /// when `runner` returns or unwinds.
+    ///
     /// ```should_panic
     /// use revent::{Node, Suspend};
     /// let node1 = Node::new(());
@@ -155,20 +206,272 @@ pub trait Suspend {
         // We do _not_ need to check the value of the borrow flag since we got `&mut`, so we know
         // it is guaranteed a mutable borrow.
         unborrow_mut(unsafe { &*last.0 });
-        let data = (runner)();
-        // unsafe: See above.
-        borrow_mut(unsafe { &*last.0 });
-        data
+        let _guard = SuspendGuard(last.0);
+        (runner)()
     }
 }
 
 impl<T> Suspend for T {}
 
+// Reborrows the suspended item when `suspend` returns or unwinds.
+struct SuspendGuard(*const Cell<BorrowFlag>);
+
+impl Drop for SuspendGuard {
+    fn drop(&mut self) {
+        // unsafe: See `Suspend::suspend`.
+        let flag = unsafe { &*self.0 };
+        // A `NodeRef` that outlives the suspension would alias the `&mut` handed back to the
+        // caller. Panicking is not enough since the panic may be caught while the caller still
+        // holds that `&mut`, so the process is aborted instead.
+        if is_borrowed(flag) {
+            eprintln!("revent: suspend: item still borrowed after suspension");
+            process::abort();
+        }
+        borrow_mut(flag);
+    }
+}
+
+// ---
+
+/// Check whether any [Node] is currently being emitted to on this thread.
+///
+/// Returns `false` when at rest, which is useful for asserting that no emission is left
+/// dangling after teardown.
+///
+/// ```
+/// use revent::{is_context_active, Node};
+///
+/// let node = Node::new(());
+/// assert!(!is_context_active());
+/// node.emit(|_| {
+///     assert!(is_context_active());
+/// });
+/// assert!(!is_context_active());
+/// ```
+pub fn is_context_active() -> bool {
+    // unsafe: See `Suspend::suspend`.
+    STACK.with(|x| !unsafe { &*x.get() }.is_empty())
+}
+
+/// Check whether the current emission is the only one in progress on this thread.
+///
+/// This is `true` exactly when actions passed to [defer] would run as soon as the current
+/// handler returns. Inside a handler it is `true` for a top-level [Node::emit] or [Slot::emit],
+/// and `false` for emissions nested within it, including those made while
+/// [suspend](Suspend::suspend)ed. It is also `false` inside the handlers of a top-level
+/// emission on a [Channel], since the channel goes on to visit its remaining items, and inside
+/// emissions made by deferred actions, which still belong to the cascade being completed.
+///
+/// ```
+/// use revent::{is_outermost_emit, Channel, Node};
+///
+/// let outer = Node::new(());
+/// let inner = Node::new(());
+/// assert!(!is_outermost_emit());
+/// outer.emit(|_| {
+///     assert!(is_outermost_emit());
+///     inner.emit(|_| {
+///         assert!(!is_outermost_emit());
+///     });
+/// });
+///
+/// let mut channel = Channel::new();
+/// channel.insert(0, Node::new(()));
+/// channel.emit(|_| {
+///     assert!(!is_outermost_emit());
+/// });
+/// ```
+pub fn is_outermost_emit() -> bool {
+    CASCADE.with(|x| x.depth.get() == 1)
+}
+
+/// Run `action` once the outermost emission on this thread has returned.
+///
+/// Useful for mutating state that may not be touched while a cascade of emissions is in
+/// progress. Deferred actions run in the order they were queued. Actions that emit, and
+/// thereby defer further actions, are handled as well: the queue is drained until empty.
+/// If no emission is in progress, `action` runs immediately.
+///
+/// The outermost emission is the outermost [Node::emit], [Slot::emit], or emission on a
+/// [Channel], so actions deferred while a channel emits run after every item has been visited.
+///
+/// Actions are discarded without being run if the outermost emission unwinds.
+///
+/// ```
+/// use revent::{defer, Channel, Node};
+/// use std::{cell::RefCell, rc::Rc};
+///
+/// let log = Rc::new(RefCell::new(Vec::new()));
+/// let mut channel = Channel::new();
+/// channel.insert(0, Node::new("first"));
+/// channel.insert(0, Node::new("second"));
+///
+/// channel.emit(|x| {
+///     let deferred = log.clone();
+///     let name = *x;
+///     defer(move || deferred.borrow_mut().push(format!("deferred {}", name)));
+///     log.borrow_mut().push(x.to_string());
+/// });
+///
+/// assert_eq!(
+///     *log.borrow(),
+///     ["first", "second", "deferred first", "deferred second"]
+/// );
+/// ```
+pub fn defer(action: impl FnOnce() + 'static) {
+    let immediate = CASCADE.with(|x| {
+        if x.depth.get() > 0 {
+            x.deferred.borrow_mut().push_back(Box::new(action));
+            None
+        } else {
+            Some(action)
+        }
+    });
+    if let Some(action) = immediate {
+        (action)();
+    }
+}
+
+// Marks an emission in progress. Deferred actions run when the outermost one ends.
+pub(crate) struct Cascade(*const Cascading);
+
+impl Cascade {
+    pub(crate) fn enter() -> Self {
+        Self::enter_cached(cascading())
+    }
+
+    // Enter through a pointer from `cascading`, avoiding the thread-local lookup.
+    pub(crate) fn enter_cached(state: *const Cascading) -> Self {
+        // unsafe: `state` points to the thread-local `CASCADE` of this thread, see `cascading`.
+        // Everything holding such a pointer is `!Send`.
+        let depth = &unsafe { &*state }.depth;
+        depth.set(depth.get() + 1);
+        Cascade(state)
+    }
+}
+
+impl Drop for Cascade {
+    fn drop(&mut self) {
+        // unsafe: See `Cascade::enter_cached`.
+        let state = unsafe { &*self.0 };
+        let _exit = CascadeExit(state);
+        if state.depth.get() != 1 {
+            return;
+        }
+
+        if thread::panicking() {
+            // Actions queued by a cascade that unwound must not run after some later emission.
+            let stale = mem::take(&mut *state.deferred.borrow_mut());
+            drop(stale);
+            return;
+        }
+
+        // The cascade stays open while flushing, so actions that emit queue further actions
+        // behind the current ones instead of flushing recursively.
+        loop {
+            let action = state.deferred.borrow_mut().pop_front();
+            match action {
+                Some(action) => (action)(),
+                None => break,
+            }
+        }
+    }
+}
+
+// Closes a cascade even if a deferred action panics.
+struct CascadeExit<'a>(&'a Cascading);
+
+impl<'a> Drop for CascadeExit<'a> {
+    fn drop(&mut self) {
+        self.0.depth.set(self.0.depth.get() - 1);
+    }
+}
+
+// ---
+
+/// Emit a method call to the contents of a [Node], [Slot], or [Channel].
+///
+/// `emit!(channel, method(a, b))` expands to `channel.emit(|x| x.method(a, b))`. The arguments
+/// are evaluated anew for each item that is emitted to.
+///
+/// ```
+/// use revent::{emit, Channel, Node};
+///
+/// trait Counter {
+///     fn add(&mut self, value: i32);
+/// }
+///
+/// impl Counter for i32 {
+///     fn add(&mut self, value: i32) {
+///         *self += value;
+///     }
+/// }
+///
+/// let mut channel = Channel::<dyn Counter>::new();
+/// channel.insert(0, Node::new(0));
+///
+/// emit!(channel, add(1));
+/// ```
+#[macro_export]
+macro_rules! emit {
+    ($target:expr, $method:ident($($argument:expr),* $(,)?)) => {
+        $target.emit(|x| x.$method($($argument),*))
+    };
+}
+
 // ---
 
+#[cfg(test)]
+mod testing {
+    use crate::{Channel, Node};
+    use std::{
+        env, panic,
+        process::{self, Command},
+    };
+
+    /// Create a channel holding the numbers `0..count` in order.
+    pub fn numbered(count: usize) -> Channel<usize> {
+        let mut channel = Channel::new();
+        for number in 0..count {
+            channel.insert(0, Node::new(number));
+        }
+        channel
+    }
+
+    /// Collect the items of a channel in emission order.
+    pub fn visited<T: Clone>(channel: &Channel<T>) -> Vec<T> {
+        let mut visited = Vec::new();
+        channel.emit(|x| visited.push(x.clone()));
+        visited
+    }
+
+    /// Run `body` in a child process that runs only the test `name`, and return the standard
+    /// error of the child after asserting that it aborted. The child exits successfully if
+    /// `body` returns or panics.
+    ///
+    /// Used to test code paths that abort the process.
+    pub fn aborted(name: &str, body: impl FnOnce()) -> String {
+        if env::var_os("REVENT_ABORT_TEST").is_some() {
+            let _ = panic::catch_unwind(panic::AssertUnwindSafe(body));
+            process::exit(0);
+        }
+
+        let output = Command::new(env::current_exe().unwrap())
+            .arg("--exact")
+            .arg(name)
+            .arg("--nocapture")
+            .arg("--test-threads=1")
+            .env("REVENT_ABORT_TEST", "1")
+            .output()
+            .unwrap();
+        assert!(!output.status.success(), "{} did not abort", name);
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::*;
+    use crate::{testing::aborted, *};
 
     #[test]
     #[should_panic(expected = "revent: suspend: not inside node context")]
@@ -240,6 +543,28 @@ mod tests {
         });
     }
 
+    #[test]
+    fn context_active_after_caught_panic() {
+        let node = Node::new(());
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            node.emit(|_| {
+                panic!();
+            });
+        }));
+        assert!(result.is_err());
+        assert!(!is_context_active());
+    }
+
+    #[test]
+    fn emit_macro() {
+        let mut slot = Slot::new();
+        slot.insert(Node::new(vec![1, 2]));
+
+        assert_eq!(emit!(slot, len()), 2);
+        emit!(slot, push(3));
+        assert_eq!(emit!(slot, clone()), [1, 2, 3]);
+    }
+
     #[test]
     #[should_panic(expected = "revent: suspend: item not expected")]
     fn suspend_overlapping_struct_check() {
@@ -269,4 +594,158 @@ mod tests {
             });
         });
     }
+
+    #[test]
+    fn deferred_after_outermost() {
+        use std::{cell::RefCell, rc::Rc};
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let outer = Node::new(());
+        let inner = Node::new(());
+
+        let nested = inner.clone();
+        let deferred = log.clone();
+        outer.emit(|_| {
+            nested.emit(|_| {
+                let log = deferred.clone();
+                let node = nested.clone();
+                defer(move || {
+                    log.borrow_mut().push(1);
+                    node.emit(|_| {
+                        let log = log.clone();
+                        defer(move || log.borrow_mut().push(3));
+                    });
+                    log.borrow_mut().push(2);
+                });
+            });
+            assert!(deferred.borrow().is_empty());
+        });
+
+        assert_eq!(*log.borrow(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn outermost_follows_cascade() {
+        use std::{cell::RefCell, rc::Rc};
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut channel = Channel::new();
+        channel.insert(0, Node::new(()));
+        let slot = Rc::new(RefCell::new(Slot::new()));
+        slot.borrow_mut().insert(Node::new(()));
+
+        let capture = log.clone();
+        let inner = slot.clone();
+        channel.emit(|_| {
+            capture.borrow_mut().push(is_outermost_emit());
+            let log = capture.clone();
+            let slot = inner.clone();
+            defer(move || {
+                log.borrow_mut().push(is_outermost_emit());
+                slot.borrow()
+                    .emit(|_| log.borrow_mut().push(is_outermost_emit()));
+            });
+        });
+        slot.borrow()
+            .emit(|_| log.borrow_mut().push(is_outermost_emit()));
+
+        assert_eq!(*log.borrow(), [false, true, false, true]);
+    }
+
+    #[test]
+    fn deferred_discarded_after_unwind() {
+        use std::{cell::Cell, panic, rc::Rc};
+
+        let ran = Rc::new(Cell::new(false));
+        let node = Node::new(());
+
+        let capture = ran.clone();
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            node.emit(|_| {
+                let ran = capture.clone();
+                defer(move || ran.set(true));
+                panic!();
+            });
+        }));
+        assert!(result.is_err());
+
+        node.emit(|_| {});
+        assert!(!ran.get());
+    }
+
+    #[test]
+    fn deferred_after_channel() {
+        use std::{cell::RefCell, rc::Rc};
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut channel = Channel::new();
+        channel.insert(0, Node::new(0));
+        channel.insert(0, Node::new(1));
+
+        let capture = log.clone();
+        channel.emit(|x| {
+            let log = capture.clone();
+            let value = *x;
+            defer(move || log.borrow_mut().push(value + 10));
+            capture.borrow_mut().push(*x);
+        });
+        assert_eq!(*log.borrow(), [0, 1, 10, 11]);
+
+        let capture = log.clone();
+        defer(move || capture.borrow_mut().push(99));
+        assert_eq!(log.borrow().last(), Some(&99));
+    }
+
+    #[test]
+    fn suspend_returning_borrow() {
+        let stderr = aborted("tests::suspend_returning_borrow", || {
+            let node = Node::new(0);
+            let other = node.clone();
+            node.emit(|x| {
+                let _escaped = x.suspend(|| other.borrow());
+            });
+        });
+        assert!(stderr.contains("revent: suspend: item still borrowed after suspension"));
+    }
+
+    #[test]
+    fn suspend_leaking_borrow_under_catch_unwind() {
+        use std::panic;
+
+        let stderr = aborted("tests::suspend_leaking_borrow_under_catch_unwind", || {
+            let node = Node::new(0);
+            let other = node.clone();
+            node.emit(|x| {
+                let mut escaped = None;
+                let _ = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+                    x.suspend(|| escaped = Some(other.borrow()));
+                }));
+                other.emit(|y| *y = 7);
+                assert_eq!(**escaped.as_ref().unwrap(), 0);
+            });
+        });
+        assert!(stderr.contains("revent: suspend: item still borrowed after suspension"));
+    }
+
+    #[test]
+    fn suspend_leaking_borrow_while_unwinding() {
+        use std::panic;
+
+        let stderr = aborted("tests::suspend_leaking_borrow_while_unwinding", || {
+            let node = Node::new(0);
+            let other = node.clone();
+            node.emit(|x| {
+                let mut escaped = None;
+                let _ = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+                    x.suspend(|| {
+                        escaped = Some(other.borrow());
+                        panic!();
+                    });
+                }));
+                other.emit(|y| *y = 7);
+                assert_eq!(**escaped.as_ref().unwrap(), 0);
+            });
+        });
+        assert!(stderr.contains("revent: suspend: item still borrowed after suspension"));
+    }
 }
@@ -1,10 +1,14 @@
-use crate::{borrow_mut, is_borrowed, unborrow_mut, BorrowFlag, Trace, STACK};
+use crate::{
+    borrow, borrow_mut, cascading, is_borrowed, is_borrowed_mut, unborrow, unborrow_mut,
+    BorrowFlag, Cascade, Cascading, Trace, STACK,
+};
 use std::{
+    any,
     cell::{Cell, UnsafeCell},
     marker::Unsize,
     mem,
-    ops::CoerceUnsized,
-    rc::Rc,
+    ops::{CoerceUnsized, Deref},
+    rc::{Rc, Weak},
 };
 
 /// Node containing arbitrary data.
@@ -18,8 +22,10 @@ use std::{
 pub struct Node<T: ?Sized> {
     item: Rc<(Cell<BorrowFlag>, UnsafeCell<T>)>,
     size: usize,
+    name: &'static str,
     trace: Trace,
     stack: *mut Vec<(*const Cell<BorrowFlag>, *mut (), usize)>,
+    cascade: *const Cascading,
 }
 
 impl<T, U> CoerceUnsized<Node<U>> for Node<T>
@@ -29,13 +35,15 @@ where
 {
 }
 
-impl<T> Clone for Node<T> {
+impl<T: ?Sized> Clone for Node<T> {
     fn clone(&self) -> Self {
         Self {
             item: self.item.clone(),
             size: self.size,
+            name: self.name,
             trace: self.trace.clone(),
             stack: STACK.with(|x| x.get()),
+            cascade: cascading(),
         }
     }
 }
@@ -46,8 +54,10 @@ impl<T> Node<T> {
         Self {
             item: Rc::new((Cell::new(0), UnsafeCell::new(item))),
             size: mem::size_of::<T>(),
+            name: any::type_name::<T>(),
             trace: Trace::empty(),
             stack: STACK.with(|x| x.get()),
+            cascade: cascading(),
         }
     }
 
@@ -61,8 +71,10 @@ impl<T> Node<T> {
         Self {
             item: Rc::new((Cell::new(0), UnsafeCell::new(item))),
             size: mem::size_of::<T>(),
+            name: any::type_name::<T>(),
             trace: Trace::new(trace),
             stack: STACK.with(|x| x.get()),
+            cascade: cascading(),
         }
     }
 }
@@ -119,11 +131,66 @@ impl<T: ?Sized> Node<T> {
         // pointee. It is also safeguarded by `self.used`, which we have proven above to be
         // `false`, otherwise we would have panicked.
         let object = unsafe { &mut *self.data().get() };
-        let data = (handler)(object);
+        let _cascade = Cascade::enter_cached(self.cascade);
+        let _guard = EmitGuard {
+            flag: self.flag(),
+            stack: self.stack,
+        };
+        (handler)(object)
+    }
 
-        unsafe { &mut *self.stack }.pop();
-        unborrow_mut(self.flag());
-        data
+    /// Acquire a shared reference to the contents of the node.
+    ///
+    /// Any number of [NodeRef]s may exist at once, but the node cannot be
+    /// [emit](Node::emit)ted to while one is alive.
+    ///
+    /// ```
+    /// use revent::Node;
+    ///
+    /// let node = Node::new(123);
+    ///
+    /// let first = node.borrow();
+    /// let second = node.borrow();
+    /// assert_eq!(*first + *second, 246);
+    /// ```
+    ///
+    /// # Panics #
+    ///
+    /// Panics if the node is currently being emitted to without being suspended. A [NodeRef]
+    /// acquired while the node is suspended must be dropped before the suspension ends, see
+    /// [suspend](crate::Suspend::suspend).
+    pub fn borrow(&self) -> NodeRef<'_, T> {
+        if is_borrowed_mut(self.flag()) {
+            panic!("revent: borrow: accessing mutably borrowed item");
+        }
+        borrow(self.flag());
+
+        NodeRef {
+            flag: self.flag(),
+            // unsafe: The flag is positive while this reference exists, so `emit` refuses to
+            // hand out a `&mut` to the same contents.
+            item: unsafe { &*self.data().get() },
+        }
+    }
+
+    /// Create a [WeakNode] pointing to the same allocation.
+    ///
+    /// The weak node does not keep the contents alive.
+    pub fn downgrade(this: &Self) -> WeakNode<T> {
+        WeakNode {
+            item: Rc::downgrade(&this.item),
+            size: this.size,
+            name: this.name,
+            trace: this.trace.clone(),
+        }
+    }
+
+    /// Name of the type this node was created with.
+    ///
+    /// The name is recorded by [new](Node::new), so it stays that of the concrete type after
+    /// the node is coerced into a `Node<dyn Trait>`. See [std::any::type_name] for the format.
+    pub fn type_name(this: &Self) -> &'static str {
+        this.name
     }
 
     /// Returns true if two `Node`s point to the same allocation.
@@ -143,6 +210,81 @@ impl<T: ?Sized> Node<T> {
     }
 }
 
+/// Non-owning reference to the contents of a [Node].
+///
+/// Created by [Node::downgrade]. Must be [upgrade](WeakNode::upgrade)d to access the contents,
+/// which fails once every [Node] pointing to the same allocation has been dropped.
+pub struct WeakNode<T: ?Sized> {
+    item: Weak<(Cell<BorrowFlag>, UnsafeCell<T>)>,
+    size: usize,
+    name: &'static str,
+    trace: Trace,
+}
+
+impl<T: ?Sized> Clone for WeakNode<T> {
+    fn clone(&self) -> Self {
+        Self {
+            item: self.item.clone(),
+            size: self.size,
+            name: self.name,
+            trace: self.trace.clone(),
+        }
+    }
+}
+
+impl<T: ?Sized> WeakNode<T> {
+    /// Attempt to acquire a [Node] from this weak reference.
+    ///
+    /// Returns `None` if the contents have been dropped.
+    pub fn upgrade(&self) -> Option<Node<T>> {
+        Some(Node {
+            item: self.item.upgrade()?,
+            size: self.size,
+            name: self.name,
+            trace: self.trace.clone(),
+            stack: STACK.with(|x| x.get()),
+            cascade: cascading(),
+        })
+    }
+}
+
+/// Shared reference to the contents of a [Node].
+///
+/// Created by [Node::borrow]. The node cannot be emitted to until this is dropped.
+pub struct NodeRef<'a, T: ?Sized> {
+    flag: &'a Cell<BorrowFlag>,
+    item: &'a T,
+}
+
+impl<'a, T: ?Sized> Deref for NodeRef<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.item
+    }
+}
+
+impl<'a, T: ?Sized> Drop for NodeRef<'a, T> {
+    fn drop(&mut self) {
+        unborrow(self.flag);
+    }
+}
+
+// Pops the stack and releases the borrow when `emit` returns or unwinds, so a node stays usable
+// after a caught panic.
+struct EmitGuard<'a> {
+    flag: &'a Cell<BorrowFlag>,
+    stack: *mut Vec<(*const Cell<BorrowFlag>, *mut (), usize)>,
+}
+
+impl<'a> Drop for EmitGuard<'a> {
+    fn drop(&mut self) {
+        // unsafe: `stack` points to the thread-local `STACK`, which outlives every node.
+        unsafe { &mut *self.stack }.pop();
+        unborrow_mut(self.flag);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::*;
@@ -158,6 +300,97 @@ mod tests {
             assert_eq!(*x, 1);
         });
     }
+
+    #[test]
+    fn borrow_blocks_emit() {
+        use std::panic;
+
+        let node = Node::new(1);
+
+        {
+            let first = node.borrow();
+            let second = node.borrow();
+            assert_eq!(*first + *second, 2);
+
+            let caught = panic::catch_unwind(panic::AssertUnwindSafe(|| node.emit(|_| {})));
+            assert!(caught.is_err());
+        }
+
+        node.emit(|x| *x += 1);
+        assert_eq!(*node.borrow(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "revent: borrow: accessing mutably borrowed item")]
+    fn borrow_inside_emit() {
+        let node = Node::new(());
+        node.emit(|_| {
+            node.borrow();
+        });
+    }
+
+    #[test]
+    fn type_name_survives_coercion() {
+        let node: Node<dyn std::fmt::Debug> = Node::new(123u8);
+        assert_eq!(Node::type_name(&node), "u8");
+        assert_eq!(Node::type_name(&node.clone()), "u8");
+    }
+
+    #[test]
+    fn downgrade_upgrade() {
+        let node = Node::new(123);
+        let weak = Node::downgrade(&node);
+
+        weak.upgrade().unwrap().emit(|x| *x = 456);
+        node.emit(|x| assert_eq!(*x, 456));
+
+        drop(node);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn emit_after_caught_panic() {
+        use std::panic;
+
+        let node = Node::new(123);
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            node.emit(|x| {
+                x.suspend(|| {
+                    panic!();
+                });
+            });
+        }));
+        assert!(result.is_err());
+
+        node.emit(|x| {
+            assert_eq!(*x, 123);
+        });
+    }
+
+    #[test]
+    fn suspend_after_caught_panic() {
+        use std::panic;
+
+        let node = Node::new(0);
+        let clone = node.clone();
+        node.emit(|x| {
+            let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+                x.suspend(|| {
+                    panic!();
+                });
+            }));
+            assert!(result.is_err());
+
+            let nested = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+                clone.emit(|_| {});
+            }));
+            assert!(nested.is_err());
+            *x += 1;
+        });
+        node.emit(|x| {
+            assert_eq!(*x, 1);
+        });
+    }
 }
 
 #[cfg(all(test, feature = "trace"))]
@@ -0,0 +1,112 @@
+use crate::{Channel, Slot};
+
+/// Sequence of fallible emissions over several channels and slots.
+///
+/// Each stage emits to a [Channel] or [Slot] of any item type. Stages run in the order they
+/// were added, and [run](Pipeline::run) stops at the first error.
+/// ```
+/// use revent::{Channel, Node, Pipeline, Slot};
+///
+/// let mut inputs = Channel::new();
+/// inputs.insert(0, Node::new(String::from("10")));
+/// inputs.insert(0, Node::new(String::from("ten")));
+///
+/// let mut total = Slot::new();
+/// total.insert(Node::new(0));
+///
+/// let mut parsed = Vec::new();
+/// let result: Result<(), &str> = Pipeline::new()
+///     .channel(&inputs, |x: &mut String| {
+///         parsed.push(x.parse::<i32>().map_err(|_| "not a number")?);
+///         Ok(())
+///     })
+///     .slot(&total, |x: &mut i32| {
+///         *x += 1;
+///         Ok(())
+///     })
+///     .run();
+///
+/// assert_eq!(result, Err("not a number"));
+/// assert_eq!(parsed, [10]);
+/// ```
+pub struct Pipeline<'a, E> {
+    stages: Vec<Box<dyn FnMut() -> Result<(), E> + 'a>>,
+}
+
+impl<'a, E> Default for Pipeline<'a, E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, E> Pipeline<'a, E> {
+    /// Create a new pipeline without stages.
+    pub fn new() -> Self {
+        Self { stages: Vec::new() }
+    }
+
+    /// Add a stage applying `handler` to each item in `channel`.
+    ///
+    /// The stage fails at the first item for which `handler` returns an error, see
+    /// [Channel::try_emit].
+    pub fn channel<T: ?Sized + 'a>(
+        mut self,
+        channel: &'a Channel<T>,
+        mut handler: impl FnMut(&mut T) -> Result<(), E> + 'a,
+    ) -> Self {
+        self.stages
+            .push(Box::new(move || channel.try_emit(|x| (handler)(x))));
+        self
+    }
+
+    /// Add a stage applying `handler` to the item in `slot`.
+    ///
+    /// # Panics #
+    ///
+    /// Running the stage panics if there exists no node in the slot.
+    pub fn slot<T: ?Sized + 'a>(
+        mut self,
+        slot: &'a Slot<T>,
+        mut handler: impl FnMut(&mut T) -> Result<(), E> + 'a,
+    ) -> Self {
+        self.stages
+            .push(Box::new(move || slot.emit(|x| (handler)(x))));
+        self
+    }
+
+    /// Run each stage in order, returning the first error.
+    ///
+    /// Stages after the failing one are not run. A pipeline can be run more than once.
+    pub fn run(&mut self) -> Result<(), E> {
+        for stage in &mut self.stages {
+            (stage)()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{testing::numbered, Node, Pipeline, Slot};
+
+    #[test]
+    fn stops_at_first_error() {
+        let first = numbered(2);
+        let second = numbered(2);
+        let mut slot = Slot::new();
+        slot.insert(Node::new(0));
+
+        let mut log = Vec::new();
+        let result = Pipeline::new()
+            .channel(&first, |x| {
+                log.push(("first", *x));
+                Ok(())
+            })
+            .slot(&slot, |_| Err("rejected"))
+            .channel(&second, |_| unreachable!())
+            .run();
+
+        assert_eq!(result, Err("rejected"));
+        assert_eq!(log, [("first", 0), ("first", 1)]);
+    }
+}
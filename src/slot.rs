@@ -52,6 +52,8 @@ impl<T: ?Sized> Slot<T> {
     ///
     /// Panics if there already exists a node in this slot.
     pub fn insert(&mut self, item: Node<T>) {
+        #[cfg(feature = "log")]
+        log::debug!("revent: insert: {} into slot", Node::type_name(&item));
         self.items = Some(item);
     }
 
@@ -61,7 +63,10 @@ impl<T: ?Sized> Slot<T> {
     ///
     /// Panics if there exists no node in this slot.
     pub fn remove(&mut self) -> Node<T> {
-        self.items.take().unwrap()
+        let item = self.items.take().unwrap();
+        #[cfg(feature = "log")]
+        log::debug!("revent: remove: {} from slot", Node::type_name(&item));
+        item
     }
 
     /// Apply a function to the node in this slot.
@@ -70,6 +75,12 @@ impl<T: ?Sized> Slot<T> {
     ///
     /// Panics if there exists no node in this slot.
     pub fn emit<R>(&self, handler: impl FnOnce(&mut T) -> R) -> R {
+        #[cfg(feature = "log")]
+        log::trace!(
+            "revent: emit: slot of {} with {} nodes",
+            std::any::type_name::<T>(),
+            self.items.iter().count()
+        );
         self.trace.log();
         Trace::indent();
 
@@ -0,0 +1,82 @@
+use crate::{Node, Slot};
+use std::{collections::HashMap, hash::Hash};
+
+/// Collection of [Slot]s keyed by `K`.
+///
+/// Useful for per-entity events where the set of keys is only known at runtime. A slot is
+/// created the first time a node is [register](SlotMap::register)ed under its key.
+/// ```
+/// use revent::{Node, SlotMap};
+///
+/// let mut map = SlotMap::new();
+///
+/// map.register("player", Node::new(100));
+/// map.register("enemy", Node::new(50));
+///
+/// assert_eq!(map.emit(&"enemy", |x| *x -= 10), Some(()));
+/// assert_eq!(map.emit(&"missing", |x| *x -= 10), None);
+/// ```
+pub struct SlotMap<K, T: ?Sized> {
+    slots: HashMap<K, Slot<T>>,
+}
+
+impl<K: Eq + Hash, T: ?Sized> Default for SlotMap<K, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Eq + Hash, T: ?Sized> SlotMap<K, T> {
+    /// Create a new slot map.
+    pub fn new() -> Self {
+        Self {
+            slots: HashMap::new(),
+        }
+    }
+
+    /// Insert a node into the slot for `key`, creating the slot if needed.
+    ///
+    /// Replaces any node previously registered under `key`.
+    pub fn register(&mut self, key: K, item: Node<T>) {
+        self.slots.entry(key).or_default().insert(item);
+    }
+
+    /// Remove the slot for `key`, returning its node.
+    pub fn remove(&mut self, key: &K) -> Option<Node<T>> {
+        self.slots.remove(key).map(|mut slot| slot.remove())
+    }
+
+    /// Apply a function to the node registered under `key`.
+    ///
+    /// Returns `None` if no node is registered under `key`.
+    pub fn emit<R>(&self, key: &K, handler: impl FnOnce(&mut T) -> R) -> Option<R> {
+        self.slots.get(key).map(|slot| slot.emit(handler))
+    }
+
+    /// Check whether a node is registered under `key`.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.slots.contains_key(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Node, SlotMap};
+
+    #[test]
+    fn register_emit_remove() {
+        let mut map = SlotMap::new();
+        map.register(1, Node::new(10));
+        map.register(2, Node::new(20));
+        map.register(1, Node::new(30));
+
+        assert_eq!(map.emit(&1, |x| *x), Some(30));
+        assert_eq!(map.emit(&2, |x| *x), Some(20));
+
+        let node = map.remove(&1).unwrap();
+        node.emit(|x| assert_eq!(*x, 30));
+        assert!(!map.contains_key(&1));
+        assert_eq!(map.emit(&1, |x| *x), None);
+        assert!(map.remove(&1).is_none());
+    }
+}